@@ -1,23 +1,64 @@
-use std::any::TypeId;
+use std::{any::TypeId, mem};
 
 use hashbrown::HashMap;
 
-use crate::component_vec::ComponentVec;
+use crate::component_vec::{ComponentVec, CHUNK_BYTES};
 
 pub struct Archetype {
 	components: HashMap<TypeId, ComponentVec>,
 	entity_count: usize,
+	reserved: bool,
 }
 impl Archetype {
 	pub fn new() -> Self {
 		Self {
 			components: HashMap::new(),
 			entity_count: 0,
+			reserved: false,
 		}
 	}
 	pub fn len(&self) -> usize {
 		self.entity_count
 	}
+	// every column uses the same rows per chunk so chunk n of each column covers the same entities
+	fn rechunk(&mut self) {
+		// each column also keeps a u32 change tick per row
+		let row_size: usize = self.components.values().map(|cv| cv.item_size() + mem::size_of::<u32>()).sum();
+		let rows = (CHUNK_BYTES / row_size.max(1)).max(1);
+		for cv in self.components.values_mut() {
+			cv.set_chunk_rows(rows);
+		}
+	}
+	pub fn chunk_rows(&self) -> usize {
+		self.components.values().next().map_or(0, |cv| cv.chunk_rows())
+	}
+	pub fn chunk_count(&self) -> usize {
+		self.components.values().map(|cv| cv.chunk_count()).max().unwrap_or(0)
+	}
+	// rows that fit before another chunk has to be allocated
+	pub fn capacity(&self) -> usize {
+		self.components.values().map(|cv| cv.capacity()).min().unwrap_or(0)
+	}
+	// a reserved table is kept by archetype collection even while empty, until it is shrunk again
+	pub fn reserve(&mut self, additional: usize) {
+		self.reserved = true;
+		self.grow(additional);
+	}
+	// makes room like `reserve` without asking for the table to be kept while empty
+	pub fn grow(&mut self, additional: usize) {
+		for cv in self.components.values_mut() {
+			cv.reserve(additional);
+		}
+	}
+	pub fn is_reserved(&self) -> bool {
+		self.reserved
+	}
+	pub fn shrink_to_fit(&mut self) {
+		self.reserved = false;
+		for cv in self.components.values_mut() {
+			cv.shrink_to_fit();
+		}
+	}
 	pub fn imitate(&self) -> Self {
 		let mut new_comps = HashMap::new();
 		for (tid, cv) in self.components.iter() {
@@ -26,6 +67,7 @@ impl Archetype {
 		Self {
 			components: new_comps,
 			entity_count: 0,
+			reserved: false,
 		}
 	}
 	pub unsafe fn publish_push(&mut self) -> usize {
@@ -33,79 +75,151 @@ impl Archetype {
 		self.entity_count += 1;
 		eid
 	}
+	pub unsafe fn publish_extend(&mut self, count: usize) -> usize {
+		let first = self.entity_count;
+		self.entity_count += count;
+		first
+	}
 	pub unsafe fn push_partial<T:'static>(&mut self, component: T) {
 		let cv = self.components.get_mut(&TypeId::of::<T>()).unwrap();
 		cv.push(component);
 	}
-	pub fn remove_entity(&mut self, entity: usize) {
-		for cv in self.components.values_mut() {
-			cv.swap_forget(entity);
+	pub fn remove_entity(&mut self, entity: usize, drop_of: impl Fn(&TypeId) -> Option<unsafe fn(*mut u8)>) {
+		for (tid, cv) in self.components.iter_mut() {
+			unsafe { cv.swap_drop(entity, drop_of(tid)) };
 		}
+		self.entity_count -= 1;
 	}
-	pub fn upgrade_entity<T:'static>(&mut self, dst: &mut Self, entity: usize, component: T) -> usize {
-		assert!((dst.components.len() - self.components.len()) == 1, "Invalid destination");
-		assert!(self.entity_count > 0, "No entity to upgrade");
+	// `drop` is run on the values of columns `dst` doesn't have
+	fn adopt_entity(&mut self, dst: &mut Self, entity: usize, drop: Option<unsafe fn(*mut u8)>) {
 		for (tid, src_cv) in self.components.iter_mut() {
-			if let Some(dst_cv) = dst.components.get_mut(&tid) {
+			if let Some(dst_cv) = dst.components.get_mut(tid) {
 				src_cv.swap_to_tail(entity);
 				dst_cv.adopt_tail(src_cv);
 			} else {
-				panic!("Destination doesn't share type {:?}", tid);
+				unsafe { src_cv.swap_drop(entity, drop) };
 			}
 		}
+		self.entity_count -= 1;
+		dst.entity_count += 1;
+	}
+	pub fn upgrade_entity<T:'static>(&mut self, dst: &mut Self, entity: usize, component: T) -> usize {
+		assert!((dst.components.len() - self.components.len()) == 1, "Invalid destination");
+		assert!(self.entity_count > 0, "No entity to upgrade");
 		if let Some(dst_cv) = dst.components.get_mut(&TypeId::of::<T>()) {
 			dst_cv.push(component);
 		} else {
 			panic!("Destination doesn't share type {:?}", TypeId::of::<T>());
 		}
-		self.entity_count -= 1;
-		dst.entity_count += 1;
+		self.adopt_entity(dst, entity, None);
 		dst.entity_count - 1
 	}
-	pub fn downgrade_entity(&mut self, dst: &mut Self, entity: usize) -> usize {
-		assert!((self.components.len() - dst.components.len()) == 1, "Invalid destination");
-		assert!(self.entity_count > 0, "No entity to downgrade");
+	pub fn upgrade_entity_with(&mut self, dst: &mut Self, entity: usize, column: &mut ComponentVec) -> usize {
+		assert!((dst.components.len() - self.components.len()) == 1, "Invalid destination");
+		assert!(self.entity_count > 0, "No entity to upgrade");
+		if let Some(dst_cv) = dst.components.get_mut(&column.type_id()) {
+			dst_cv.adopt_tail(column);
+		} else {
+			panic!("Destination doesn't share type {:?}", column.type_id());
+		}
+		self.adopt_entity(dst, entity, None);
+		dst.entity_count - 1
+	}
+	// moves into a superset, taking the missing components from the tails of `columns`
+	pub fn upgrade_entity_many(&mut self, dst: &mut Self, entity: usize, columns: &mut [&mut ComponentVec]) -> usize {
+		assert!(dst.components.len() == self.components.len() + columns.len(), "Invalid destination");
+		assert!(self.entity_count > 0, "No entity to upgrade");
+		for column in columns.iter_mut() {
+			let dst_cv = dst.components.get_mut(&column.type_id()).expect("Destination doesn't share type");
+			dst_cv.adopt_tail(column);
+		}
+		self.adopt_entity(dst, entity, None);
+		dst.entity_count - 1
+	}
+	// moves every row into `dst` at once, columns `dst` lacks are dropped and `extra` fills the ones it adds
+	pub fn move_all(&mut self, dst: &mut Self, extra: &mut [ComponentVec], drop_of: impl Fn(&TypeId) -> Option<unsafe fn(*mut u8)>) -> usize {
+		assert!(extra.iter().all(|c| c.len() == self.entity_count), "Column length doesn't match the table");
+		let first = dst.entity_count;
 		for (tid, src_cv) in self.components.iter_mut() {
-			if let Some(dst_cv) = dst.components.get_mut(&tid) {
-				src_cv.swap_to_tail(entity);
-				dst_cv.adopt_tail(src_cv);
+			if let Some(dst_cv) = dst.components.get_mut(tid) {
+				dst_cv.append(src_cv);
+			} else {
+				unsafe { src_cv.drop_elements(drop_of(tid)) };
 			}
 		}
-		self.entity_count -= 1;
-		dst.entity_count += 1;
+		for column in extra.iter_mut() {
+			dst.components.get_mut(&column.type_id()).expect("Destination doesn't share type").append(column);
+		}
+		dst.entity_count += self.entity_count;
+		self.entity_count = 0;
+		first
+	}
+	pub fn clear(&mut self, drop_of: impl Fn(&TypeId) -> Option<unsafe fn(*mut u8)>) {
+		for (tid, cv) in self.components.iter_mut() {
+			unsafe { cv.drop_elements(drop_of(tid)) };
+		}
+		self.entity_count = 0;
+	}
+	// the removed component is dropped with `drop`, None leaves it to whoever copied it out
+	pub fn downgrade_entity(&mut self, dst: &mut Self, entity: usize, drop: Option<unsafe fn(*mut u8)>) -> usize {
+		assert!((self.components.len() - dst.components.len()) == 1, "Invalid destination");
+		assert!(self.entity_count > 0, "No entity to downgrade");
+		self.adopt_entity(dst, entity, drop);
 		dst.entity_count - 1
 	}
 	pub fn add_component<T:'static>(&mut self) -> &mut Self {
 		assert!(self.entity_count == 0, "Cannot modify component list while occupied");
 		self.components.insert(TypeId::of::<T>(), ComponentVec::new_as::<T>());
+		self.rechunk();
+		self
+	}
+	pub fn add_column(&mut self, column: &ComponentVec) -> &mut Self {
+		assert!(self.entity_count == 0, "Cannot modify component list while occupied");
+		self.components.insert(column.type_id(), column.imitate());
+		self.rechunk();
 		self
 	}
 	pub fn remove_component<T:'static>(&mut self) -> &mut Self {
+		self.remove_column(&TypeId::of::<T>())
+	}
+	pub fn remove_column(&mut self, tid: &TypeId) -> &mut Self {
 		assert!(self.entity_count == 0, "Cannot modify component list while occupied");
-		self.components.remove(&TypeId::of::<T>());
+		self.components.remove(tid);
+		self.rechunk();
 		self
 	}
-	pub fn get_component<T:'static>(&self, entity: usize) -> Option<&T> {
-		if let Some(cv) = self.components.get(&TypeId::of::<T>()) {
-			Some(cv.as_slice()[entity])
-		} else {
-			None
+	pub fn types(&self) -> impl Iterator<Item = &TypeId> {
+		self.components.keys()
+	}
+	pub fn get_column(&self, tid: &TypeId) -> Option<&ComponentVec> {
+		self.components.get(tid)
+	}
+	pub fn get_column_mut(&mut self, tid: &TypeId) -> Option<&mut ComponentVec> {
+		self.components.get_mut(tid)
+	}
+	// several columns at once, in the order of `tids`, which have to be distinct
+	pub fn get_columns_mut(&mut self, tids: &[TypeId]) -> Option<Vec<&mut ComponentVec>> {
+		let mut columns: Vec<Option<&mut ComponentVec>> = tids.iter().map(|_| None).collect();
+		for (tid, cv) in self.components.iter_mut() {
+			if let Some(i) = tids.iter().position(|t| t == tid) {
+				columns[i] = Some(cv);
+			}
 		}
+		columns.into_iter().collect()
+	}
+	pub fn get_component<T:'static>(&self, entity: usize) -> Option<&T> {
+		self.components.get(&TypeId::of::<T>()).and_then(|cv| cv.get(entity))
 	}
 	pub fn get_component_mut<T:'static>(&mut self, entity: usize) -> Option<&mut T> {
-		if let Some(cv) = self.components.get_mut(&TypeId::of::<T>()) {
-			Some(&mut cv.as_mut_slice()[entity])
-		} else {
-			None
-		}
+		self.components.get_mut(&TypeId::of::<T>()).and_then(|cv| cv.get_mut(entity))
 	}
 	#[allow(dead_code)]
-	pub fn get_component_vec<T:'static>(&self) -> &[T] {
-		self.components.get(&TypeId::of::<T>()).unwrap().as_slice::<T>()
+	pub fn get_component_vec<T:'static>(&self) -> impl Iterator<Item = &T> {
+		self.components.get(&TypeId::of::<T>()).unwrap().iter::<T>()
 	}
 	#[allow(dead_code)]
-	pub fn get_component_vec_mut<T:'static>(&mut self) -> &mut [T] {
-		self.components.get_mut(&TypeId::of::<T>()).unwrap().as_mut_slice::<T>()
+	pub fn get_component_vec_mut<T:'static>(&mut self) -> impl Iterator<Item = &mut T> {
+		self.components.get_mut(&TypeId::of::<T>()).unwrap().iter_mut::<T>()
 	}
 	#[allow(dead_code)]
 	pub fn get_many_comp_vec_mut<const W:usize>(&mut self, types: [&TypeId;W]) -> [&mut ComponentVec;W] {
@@ -125,9 +239,13 @@ macro_rules! archetype {
 #[macro_export]
 macro_rules! push_entity {
 	($a:expr, [$($comp:expr),+]) => {
-		unsafe { 
-			$( $a.push_partial($comp); )+ 
-			$a.publish_push()
+		{
+			let a = $a;
+			$(
+				let comp = $comp;
+				unsafe { a.push_partial(comp); }
+			)+
+			unsafe { a.publish_push() }
 		}
 	};
 }
\ No newline at end of file
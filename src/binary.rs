@@ -0,0 +1,304 @@
+use std::{any::TypeId, fmt};
+
+use hashbrown::HashSet;
+
+use crate::{component_vec::{ComponentVec, OwnedColumn}, ComponentInfo, EntityId, MigrationError, StableId, ECS};
+
+const MAGIC: &[u8; 4] = b"AECS";
+// 1 named component types, 2 switched to stable ids, 3 added per type schema versions
+const FORMAT_VERSION: u32 = 3;
+
+/// # Safety
+/// Columns of `Pod` types are saved and restored as raw bytes, so the type must not
+/// hold pointers, references or anything else that is only valid within one process.
+pub unsafe trait Pod: Copy + 'static {}
+macro_rules! impl_pod {
+	($($t:ty),*) => { $( unsafe impl Pod for $t {} )* };
+}
+impl_pod!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64, bool, char, ());
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
+unsafe impl Pod for EntityId {}
+
+pub trait BinaryCodec: Sized + 'static {
+	fn encode(&self, out: &mut Vec<u8>);
+	fn decode(input: &mut BinaryReader) -> Result<Self, BinaryError>;
+}
+impl BinaryCodec for String {
+	fn encode(&self, out: &mut Vec<u8>) {
+		put_str(out, self);
+	}
+	fn decode(input: &mut BinaryReader) -> Result<Self, BinaryError> {
+		input.str()
+	}
+}
+
+#[derive(Debug)]
+pub enum BinaryError {
+	BadMagic,
+	UnsupportedVersion(u32),
+	UnexpectedEof,
+	UnknownComponent(StableId),
+	NotBinary(String),
+	LayoutMismatch(String),
+	DuplicateEntity(EntityId),
+	DuplicateComponent(StableId),
+	Journaling,
+	Migration(MigrationError),
+	Codec(String),
+}
+impl fmt::Display for BinaryError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			BinaryError::BadMagic => write!(f, "not an aecs snapshot"),
+			BinaryError::UnsupportedVersion(v) => write!(f, "unsupported snapshot version {}", v),
+			BinaryError::UnexpectedEof => write!(f, "snapshot ended unexpectedly"),
+			BinaryError::UnknownComponent(id) => write!(f, "no component registered as {:?}", id),
+			BinaryError::NotBinary(name) => write!(f, "component `{}` has no pod or codec registration", name),
+			BinaryError::LayoutMismatch(name) => write!(f, "component `{}` changed layout", name),
+			BinaryError::DuplicateEntity(e) => write!(f, "entity {:?} already exists", e),
+			BinaryError::DuplicateComponent(id) => write!(f, "component {:?} is listed twice in one archetype", id),
+			BinaryError::Journaling => write!(f, "snapshots can't be loaded while a journal is recording"),
+			BinaryError::Migration(e) => write!(f, "{}", e),
+			BinaryError::Codec(msg) => write!(f, "{}", msg),
+		}
+	}
+}
+impl std::error::Error for BinaryError {}
+impl From<MigrationError> for BinaryError {
+	fn from(e: MigrationError) -> Self {
+		BinaryError::Migration(e)
+	}
+}
+
+pub(crate) fn put_u32(out: &mut Vec<u8>, v: u32) {
+	out.extend_from_slice(&v.to_le_bytes());
+}
+pub(crate) fn put_u64(out: &mut Vec<u8>, v: u64) {
+	out.extend_from_slice(&v.to_le_bytes());
+}
+fn put_str(out: &mut Vec<u8>, v: &str) {
+	put_u64(out, v.len() as u64);
+	out.extend_from_slice(v.as_bytes());
+}
+
+pub struct BinaryReader<'a> {
+	bytes: &'a [u8],
+}
+impl<'a> BinaryReader<'a> {
+	pub fn new(bytes: &'a [u8]) -> Self {
+		Self { bytes }
+	}
+	pub fn take(&mut self, n: usize) -> Result<&'a [u8], BinaryError> {
+		if self.bytes.len() < n {
+			return Err(BinaryError::UnexpectedEof);
+		}
+		let (head, tail) = self.bytes.split_at(n);
+		self.bytes = tail;
+		Ok(head)
+	}
+	pub fn u8(&mut self) -> Result<u8, BinaryError> {
+		Ok(self.take(1)?[0])
+	}
+	pub fn u32(&mut self) -> Result<u32, BinaryError> {
+		Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+	}
+	pub fn u64(&mut self) -> Result<u64, BinaryError> {
+		Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+	}
+	pub fn str(&mut self) -> Result<String, BinaryError> {
+		let len = self.u64()? as usize;
+		String::from_utf8(self.take(len)?.to_vec()).map_err(|e| BinaryError::Codec(e.to_string()))
+	}
+}
+
+pub(crate) fn encode_column<T: BinaryCodec>(cv: &ComponentVec, out: &mut Vec<u8>) {
+	for el in cv.iter::<T>() {
+		el.encode(out);
+	}
+}
+pub(crate) fn decode_column<T: BinaryCodec>(input: &mut BinaryReader, count: usize, dst: &mut ComponentVec) -> Result<(), BinaryError> {
+	// the count is untrusted, never reserve more rows than there are bytes left
+	dst.reserve(count.min(input.bytes.len()));
+	for _ in 0..count {
+		dst.push(T::decode(input)?);
+	}
+	Ok(())
+}
+
+fn read_column(info: &ComponentInfo, version: u32, input: &mut BinaryReader, count: usize, dst: &mut ComponentVec) -> Result<(), BinaryError> {
+	let len = input.u64()? as usize;
+	let mut bytes = input.take(len)?;
+	let migrated;
+	if version != info.version() {
+		migrated = info.migrate_binary(bytes.to_vec(), count, version)?;
+		bytes = &migrated;
+	}
+	if info.is_pod() {
+		let expected = count.checked_mul(info.size()).ok_or_else(|| BinaryError::Codec(format!("column of {} rows is too large", count)))?;
+		if bytes.len() != expected {
+			return Err(BinaryError::LayoutMismatch(info.name().to_string()));
+		}
+		unsafe { dst.extend_from_bytes(bytes, count) };
+		Ok(())
+	} else {
+		info.decode_into(&mut BinaryReader::new(bytes), count, dst).unwrap()
+	}
+}
+
+impl ECS {
+	pub fn save_binary(&self) -> Vec<u8> {
+		let mut types: Vec<&ComponentInfo> = Vec::new();
+		let mut archetypes = Vec::new();
+		for (_, arche) in self.archetypes.iter() {
+			if arche.len() == 0 {
+				continue;
+			}
+			let mut columns: Vec<_> = arche.types()
+				.filter(|tid| **tid != TypeId::of::<EntityId>())
+				.filter_map(|tid| self.registry.get(tid))
+				.filter(|info| info.is_binary())
+				.collect();
+			columns.sort_by_key(|info| info.stable_id());
+			for info in columns.iter() {
+				if !types.iter().any(|t| t.type_id() == info.type_id()) {
+					types.push(info);
+				}
+			}
+			archetypes.push((arche, columns));
+		}
+
+		let mut out = Vec::new();
+		out.extend_from_slice(MAGIC);
+		put_u32(&mut out, FORMAT_VERSION);
+		put_u32(&mut out, types.len() as u32);
+		for info in types.iter() {
+			put_u64(&mut out, info.stable_id().get());
+			put_u32(&mut out, info.version());
+			put_u32(&mut out, info.size() as u32);
+			put_u32(&mut out, info.align() as u32);
+			out.push(info.is_pod() as u8);
+		}
+		put_u32(&mut out, archetypes.len() as u32);
+		let mut scratch = Vec::new();
+		for (arche, columns) in archetypes {
+			put_u32(&mut out, columns.len() as u32);
+			for info in columns.iter() {
+				put_u32(&mut out, types.iter().position(|t| t.type_id() == info.type_id()).unwrap() as u32);
+			}
+			put_u64(&mut out, arche.len() as u64);
+			for bytes in arche.get_column(&TypeId::of::<EntityId>()).unwrap().byte_chunks() {
+				out.extend_from_slice(bytes);
+			}
+			for info in columns.iter() {
+				let cv = arche.get_column(&info.type_id()).unwrap();
+				if info.is_pod() {
+					put_u64(&mut out, (cv.len() * cv.item_size()) as u64);
+					for bytes in cv.byte_chunks() {
+						out.extend_from_slice(bytes);
+					}
+				} else {
+					scratch.clear();
+					info.encode(cv, &mut scratch);
+					put_u64(&mut out, scratch.len() as u64);
+					out.extend_from_slice(&scratch);
+				}
+			}
+		}
+		out
+	}
+	pub fn load_binary(&mut self, bytes: &[u8]) -> Result<usize, BinaryError> {
+		if self.is_journaling() {
+			return Err(BinaryError::Journaling);
+		}
+		let mut input = BinaryReader::new(bytes);
+		if input.take(4)? != MAGIC {
+			return Err(BinaryError::BadMagic);
+		}
+		let format = input.u32()?;
+		if format == 0 || format > FORMAT_VERSION {
+			return Err(BinaryError::UnsupportedVersion(format));
+		}
+
+		let mut types = Vec::new();
+		for _ in 0..input.u32()? {
+			let info = if format == 1 {
+				let name = input.str()?;
+				self.registry.get_by_name(&name).ok_or_else(|| BinaryError::UnknownComponent(StableId::from_name(&name)))?
+			} else {
+				let id = StableId::new(input.u64()?);
+				self.registry.get_by_stable(id).ok_or(BinaryError::UnknownComponent(id))?
+			};
+			// data written before schema versions existed is at version 0
+			let version = if format >= 3 { input.u32()? } else { 0 };
+			let (size, align, pod) = (input.u32()? as usize, input.u32()? as usize, input.u8()? != 0);
+			let name = info.name().to_string();
+			// the id column is always there, it can't come a second time as data
+			if info.type_id() == TypeId::of::<EntityId>() {
+				return Err(BinaryError::DuplicateComponent(info.stable_id()));
+			}
+			if !info.is_binary() {
+				return Err(BinaryError::NotBinary(name));
+			}
+			// an older layout is fine as long as migrations can bring it up to date
+			if info.is_pod() != pod || (pod && version == info.version() && (info.size() != size || info.align() != align)) {
+				return Err(BinaryError::LayoutMismatch(name));
+			}
+			types.push((info.type_id(), version));
+		}
+
+		// decode every column before touching the world so a bad snapshot has no effect
+		let mut staged = Vec::new();
+		let mut seen = HashSet::new();
+		for _ in 0..input.u32()? {
+			let mut signature: Vec<(TypeId, u32)> = Vec::new();
+			for _ in 0..input.u32()? {
+				let idx = input.u32()? as usize;
+				let (tid, version) = *types.get(idx).ok_or_else(|| BinaryError::Codec(format!("type index {} out of range", idx)))?;
+				if signature.iter().any(|(other, _)| *other == tid) {
+					return Err(BinaryError::DuplicateComponent(self.registry.get(&tid).unwrap().stable_id()));
+				}
+				signature.push((tid, version));
+			}
+			let count = input.u64()? as usize;
+			let len = count.checked_mul(std::mem::size_of::<EntityId>())
+				.ok_or_else(|| BinaryError::Codec(format!("archetype of {} rows is too large", count)))?;
+			let mut ids = ComponentVec::new_as::<EntityId>();
+			unsafe { ids.extend_from_bytes(input.take(len)?, count) };
+			for id in ids.iter::<EntityId>() {
+				if self.entity_index.contains_key(id) || !seen.insert(*id) {
+					return Err(BinaryError::DuplicateEntity(*id));
+				}
+			}
+			let mut columns = Vec::with_capacity(signature.len());
+			for (tid, version) in signature.iter() {
+				let info = self.registry.get(tid).unwrap();
+				// owned so the rows decoded so far are dropped if a later read fails
+				let mut column = OwnedColumn { data: info.new_column(), drop: info.drop_fn() };
+				read_column(info, *version, &mut input, count, &mut column.data)?;
+				columns.push(column);
+			}
+			let signature: Vec<TypeId> = signature.into_iter().map(|(tid, _)| tid).collect();
+			staged.push((signature, ids, columns));
+		}
+
+		let mut total = 0;
+		for (signature, mut ids, columns) in staged {
+			let a_id = self.get_or_create_archetype(&signature);
+			let arche = self.archetypes.get_mut(&a_id).unwrap();
+			let count = ids.len();
+			let first = unsafe { arche.publish_extend(count) };
+			for (row, id) in ids.iter::<EntityId>().enumerate() {
+				self.entity_ids.skip_past(id.0);
+				self.entity_index.insert(*id, (a_id, first + row));
+			}
+			arche.get_column_mut(&TypeId::of::<EntityId>()).unwrap().append(&mut ids);
+			for column in columns {
+				let mut column = column.into_inner();
+				column.mark_all_changed(self.change_tick);
+				arche.get_column_mut(&column.type_id()).unwrap().append(&mut column);
+			}
+			total += count;
+		}
+		Ok(total)
+	}
+}
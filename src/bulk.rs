@@ -0,0 +1,104 @@
+use std::any::TypeId;
+
+use crate::{component_vec::ComponentVec, ArchetypeId, EntityId, ECS};
+
+// structural changes applied to whole tables, every matching archetype moves in one go
+impl ECS {
+	// recorded or sparse changes go entity by entity so the journal and undo history see each of them
+	fn bulk_allowed(&self, types: &[TypeId]) -> bool {
+		!self.is_recording_undo() && !self.is_journaling() && !types.iter().any(|tid| self.is_sparse(tid))
+	}
+	fn matching_archetypes(&self, with: &[TypeId]) -> Vec<ArchetypeId> {
+		self.archetypes.iter()
+			.filter(|(_, arche)| arche.len() > 0 && with.iter().all(|tid| arche.get_column(tid).is_some()))
+			.map(|(a_id, _)| a_id)
+			.collect()
+	}
+	fn move_table(&mut self, src: ArchetypeId, dst: ArchetypeId, extra: &mut [ComponentVec]) {
+		let registry = &self.registry;
+		let [src_arche, dst_arche] = self.archetypes.get_many([&src, &dst]).unwrap();
+		let first = src_arche.move_all(dst_arche, extra, |tid| registry.get(tid).and_then(|info| info.drop_fn()));
+		for (row, entity) in dst_arche.get_component_vec::<EntityId>().enumerate().skip(first) {
+			self.entity_index.insert(*entity, (dst, row));
+		}
+	}
+	// entities that already have a `T` get theirs overwritten, returns how many entities were touched
+	pub fn insert_for_query<T:'static + Clone>(&mut self, with: &[TypeId], value: T) -> usize {
+		self.registry.register::<T>();
+		let tid = TypeId::of::<T>();
+		if !self.bulk_allowed(&[with, &[tid]].concat()) {
+			let entities: Vec<EntityId> = self.entities_with(with).collect();
+			for e in entities.iter() {
+				self.attach_component(*e, value.clone());
+			}
+			return entities.len();
+		}
+		// tables that already hold `T` go first, so rows moved into them later aren't visited twice
+		let mut matching = self.matching_archetypes(with);
+		matching.sort_by_key(|a_id| self.archetypes.get(a_id).unwrap().get_column(&tid).is_none());
+		let mut count = 0;
+		for a_id in matching {
+			let arche = self.archetypes.get_mut(&a_id).unwrap();
+			count += arche.len();
+			if let Some(column) = arche.get_column_mut(&tid) {
+				column.iter_mut::<T>().for_each(|c| *c = value.clone());
+				column.mark_all_changed(self.change_tick);
+				continue;
+			}
+			let mut column = ComponentVec::new_as::<T>();
+			column.reserve(arche.len());
+			for _ in 0..arche.len() {
+				column.push(value.clone());
+			}
+			column.mark_all_changed(self.change_tick);
+			let mut signature: Vec<TypeId> = arche.types().copied().collect();
+			signature.push(tid);
+			let dst = self.get_or_create_archetype(&signature);
+			self.move_table(a_id, dst, &mut [column]);
+		}
+		count
+	}
+	pub fn remove_for_query<T:'static>(&mut self, with: &[TypeId]) -> usize {
+		let tid = TypeId::of::<T>();
+		if !self.bulk_allowed(&[with, &[tid]].concat()) {
+			let entities: Vec<EntityId> = self.entities_with(with).filter(|e| self.has_component::<T>(*e)).collect();
+			for e in entities.iter() {
+				self.detach_type(*e, &tid);
+			}
+			return entities.len();
+		}
+		let mut count = 0;
+		for a_id in self.matching_archetypes(&[with, &[tid]].concat()) {
+			let arche = self.archetypes.get(&a_id).unwrap();
+			count += arche.len();
+			let signature: Vec<TypeId> = arche.types().copied().filter(|t| *t != tid).collect();
+			let dst = self.get_or_create_archetype(&signature);
+			self.move_table(a_id, dst, &mut []);
+		}
+		count
+	}
+	pub fn despawn_query(&mut self, with: &[TypeId]) -> usize {
+		if !self.bulk_allowed(with) {
+			let entities: Vec<EntityId> = self.entities_with(with).collect();
+			for e in entities.iter() {
+				self.destroy_entity(*e);
+			}
+			return entities.len();
+		}
+		let mut count = 0;
+		for a_id in self.matching_archetypes(with) {
+			let arche = self.archetypes.get(&a_id).unwrap();
+			let entities: Vec<EntityId> = arche.get_component_vec::<EntityId>().copied().collect();
+			let registry = &self.registry;
+			for e in entities.iter() {
+				self.entity_index.remove(e);
+				for (tid, set) in self.sparse.iter_mut() {
+					set.remove(*e, registry.get(tid).and_then(|info| info.drop_fn()));
+				}
+			}
+			self.archetypes.get_mut(&a_id).unwrap().clear(|tid| registry.get(tid).and_then(|info| info.drop_fn()));
+			count += entities.len();
+		}
+		count
+	}
+}
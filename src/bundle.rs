@@ -0,0 +1,69 @@
+use std::any::TypeId;
+
+use crate::{component_vec::ComponentVec, ComponentRegistry, EntityId};
+
+// a set of component types named together, e.g. `(Pos, Vel)`, to size or find their archetype up front
+pub trait Bundle: 'static {
+	fn register(registry: &mut ComponentRegistry);
+	fn type_ids() -> Vec<TypeId>;
+	fn new_columns() -> Vec<ComponentVec>;
+	// pushes one value onto each column, in `type_ids` order
+	fn write(self, columns: &mut [&mut ComponentVec]);
+	fn into_columns(self) -> Vec<ComponentVec> where Self: Sized {
+		let mut columns = Self::new_columns();
+		self.write(&mut columns.iter_mut().collect::<Vec<_>>());
+		columns
+	}
+}
+// rows a batch wrote into live columns are taken out again if the batch doesn't finish
+pub(crate) struct BatchGuard<'a> {
+	pub columns: Vec<&'a mut ComponentVec>,
+	pub drops: Vec<Option<unsafe fn(*mut u8)>>,
+	pub first: usize,
+}
+impl BatchGuard<'_> {
+	pub fn finish(mut self) {
+		self.columns.clear();
+	}
+}
+impl Drop for BatchGuard<'_> {
+	fn drop(&mut self) {
+		for (column, drop) in self.columns.iter_mut().zip(self.drops.iter()) {
+			unsafe { column.truncate(self.first, *drop) };
+		}
+	}
+}
+
+macro_rules! impl_bundle {
+	($($t:ident),+) => {
+		#[allow(non_snake_case)]
+		impl<$($t: 'static),+> Bundle for ($($t,)+) {
+			fn register(registry: &mut ComponentRegistry) {
+				$( registry.register::<$t>(); )+
+			}
+			fn type_ids() -> Vec<TypeId> {
+				let tids = vec![$(TypeId::of::<$t>()),+];
+				assert!(tids.iter().enumerate().all(|(i, tid)| !tids[..i].contains(tid)), "Bundle contains the same component type twice");
+				// every entity already has its own id column
+				assert!(!tids.contains(&TypeId::of::<EntityId>()), "Bundle can't contain an EntityId");
+				tids
+			}
+			fn new_columns() -> Vec<ComponentVec> {
+				vec![$(ComponentVec::new_as::<$t>()),+]
+			}
+			fn write(self, columns: &mut [&mut ComponentVec]) {
+				let ($($t,)+) = self;
+				let mut columns = columns.iter_mut();
+				$( columns.next().unwrap().push($t); )+
+			}
+		}
+	};
+}
+impl_bundle!(A);
+impl_bundle!(A, B);
+impl_bundle!(A, B, C);
+impl_bundle!(A, B, C, D);
+impl_bundle!(A, B, C, D, E);
+impl_bundle!(A, B, C, D, E, F);
+impl_bundle!(A, B, C, D, E, F, G);
+impl_bundle!(A, B, C, D, E, F, G, H);
@@ -0,0 +1,210 @@
+use std::collections::BTreeMap;
+
+use hashbrown::HashMap;
+
+use crate::{ArchetypeId, EntityId, StableId, ECS};
+
+// FNV-1a, chosen over std's hashers because their output is allowed to change between releases
+pub struct StableHasher(u64);
+impl Default for StableHasher {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+impl StableHasher {
+	pub fn new() -> Self {
+		Self(0xcbf29ce484222325)
+	}
+	pub fn write(&mut self, bytes: &[u8]) {
+		for b in bytes {
+			self.0 ^= *b as u64;
+			self.0 = self.0.wrapping_mul(0x100000001b3);
+		}
+	}
+	pub fn write_u64(&mut self, v: u64) {
+		self.write(&v.to_le_bytes());
+	}
+	pub fn finish(&self) -> u64 {
+		self.0
+	}
+}
+
+pub trait StableHash {
+	fn stable_hash(&self, state: &mut StableHasher);
+}
+macro_rules! impl_stable_hash {
+	($($t:ty),*) => {
+		$( impl StableHash for $t {
+			fn stable_hash(&self, state: &mut StableHasher) {
+				state.write(&self.to_le_bytes());
+			}
+		} )*
+	};
+}
+impl_stable_hash!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128);
+impl StableHash for usize {
+	fn stable_hash(&self, state: &mut StableHasher) {
+		state.write_u64(*self as u64);
+	}
+}
+impl StableHash for isize {
+	fn stable_hash(&self, state: &mut StableHasher) {
+		state.write_u64(*self as i64 as u64);
+	}
+}
+impl StableHash for f32 {
+	fn stable_hash(&self, state: &mut StableHasher) {
+		state.write(&self.to_bits().to_le_bytes());
+	}
+}
+impl StableHash for f64 {
+	fn stable_hash(&self, state: &mut StableHasher) {
+		state.write(&self.to_bits().to_le_bytes());
+	}
+}
+impl StableHash for bool {
+	fn stable_hash(&self, state: &mut StableHasher) {
+		state.write(&[*self as u8]);
+	}
+}
+impl StableHash for char {
+	fn stable_hash(&self, state: &mut StableHasher) {
+		(*self as u32).stable_hash(state);
+	}
+}
+impl StableHash for str {
+	fn stable_hash(&self, state: &mut StableHasher) {
+		state.write_u64(self.len() as u64);
+		state.write(self.as_bytes());
+	}
+}
+impl StableHash for String {
+	fn stable_hash(&self, state: &mut StableHasher) {
+		self.as_str().stable_hash(state);
+	}
+}
+impl StableHash for EntityId {
+	fn stable_hash(&self, state: &mut StableHasher) {
+		state.write(self.0.as_bytes());
+	}
+}
+impl<T: StableHash> StableHash for Option<T> {
+	fn stable_hash(&self, state: &mut StableHasher) {
+		match self {
+			Some(v) => {
+				state.write(&[1]);
+				v.stable_hash(state);
+			},
+			None => state.write(&[0]),
+		}
+	}
+}
+impl<T: StableHash> StableHash for [T] {
+	fn stable_hash(&self, state: &mut StableHasher) {
+		state.write_u64(self.len() as u64);
+		for v in self {
+			v.stable_hash(state);
+		}
+	}
+}
+impl<T: StableHash, const N: usize> StableHash for [T; N] {
+	fn stable_hash(&self, state: &mut StableHasher) {
+		self[..].stable_hash(state);
+	}
+}
+impl<T: StableHash> StableHash for Vec<T> {
+	fn stable_hash(&self, state: &mut StableHasher) {
+		self[..].stable_hash(state);
+	}
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WorldChecksum {
+	pub total: u64,
+	pub components: BTreeMap<StableId, u64>,
+}
+impl WorldChecksum {
+	pub fn diverging_components(&self, other: &Self) -> Vec<StableId> {
+		let mut ids: Vec<StableId> = self.components.keys().chain(other.components.keys()).copied().collect();
+		ids.sort();
+		ids.dedup();
+		ids.retain(|id| self.components.get(id) != other.components.get(id));
+		ids
+	}
+}
+
+impl ECS {
+	pub fn checksum(&self) -> u64 {
+		self.checksum_by_component().total
+	}
+	pub fn checksum_by_component(&self) -> WorldChecksum {
+		// archetypes are visited by their sorted stable signature, rows in storage order
+		let mut archetypes: Vec<_> = self.archetypes.iter()
+			.map(|(a_id, arche)| {
+				let mut signature: Vec<StableId> = arche.types()
+					.filter_map(|tid| self.registry.get(tid))
+					.map(|info| info.stable_id())
+					.collect();
+				signature.sort();
+				(signature, arche, a_id)
+			})
+			.filter(|(_, arche, _)| arche.len() > 0)
+			.collect();
+		archetypes.sort_by(|a, b| a.0.cmp(&b.0));
+		let order: HashMap<ArchetypeId, usize> = archetypes.iter().enumerate().map(|(i, (.., a_id))| (*a_id, i)).collect();
+
+		let mut total = StableHasher::new();
+		let mut components = BTreeMap::new();
+		for (signature, arche, _) in archetypes.iter() {
+			for id in signature.iter() {
+				total.write_u64(id.get());
+			}
+			total.write_u64(arche.len() as u64);
+			for id in signature.iter() {
+				let info = self.registry.get_by_stable(*id).unwrap();
+				if !info.has_stable_hash() {
+					continue;
+				}
+				let cv = arche.get_column(&info.type_id()).unwrap();
+				let hasher = components.entry(*id).or_insert_with(StableHasher::new);
+				for row in 0..cv.len() {
+					info.stable_hash(cv, row, hasher);
+				}
+			}
+		}
+		// sparse rows are visited in the order of their owners' table rows, the set's own order depends
+		// on its history and ids can differ between otherwise identical worlds
+		let mut sparse: Vec<_> = self.sparse.iter()
+			.filter(|(_, set)| set.len() > 0)
+			.filter_map(|(tid, set)| Some((self.registry.get(tid)?, set)))
+			.collect();
+		sparse.sort_by_key(|(info, _)| info.stable_id());
+		for (info, set) in sparse {
+			let mut rows: Vec<((usize, usize), usize)> = set.entities().iter().enumerate()
+				.map(|(row, e)| {
+					let (a_id, owner) = self.entity_index[e];
+					((order[&a_id], owner), row)
+				})
+				.collect();
+			rows.sort_unstable();
+			total.write_u64(info.stable_id().get());
+			total.write_u64(rows.len() as u64);
+			for ((table, owner), _) in rows.iter() {
+				total.write_u64(*table as u64);
+				total.write_u64(*owner as u64);
+			}
+			if info.has_stable_hash() {
+				let hasher = components.entry(info.stable_id()).or_insert_with(StableHasher::new);
+				for (_, row) in rows {
+					info.stable_hash(set.column(), row, hasher);
+				}
+			}
+		}
+		let components: BTreeMap<StableId, u64> = components.into_iter().map(|(id, h)| (id, h.finish())).collect();
+		for (id, hash) in components.iter() {
+			total.write_u64(id.get());
+			total.write_u64(*hash);
+		}
+		WorldChecksum { total: total.finish(), components }
+	}
+}
@@ -1,20 +1,32 @@
 use std::{mem, ptr::{NonNull, self}, marker::PhantomData, any::TypeId, alloc::{Layout, self}};
 
-struct RawVec {
-	ptr: NonNull<u8>,
-	cap: usize,
+// target size of one chunk, archetypes pick a row count that keeps a whole row set around this
+pub const CHUNK_BYTES: usize = 16 * 1024;
+
+// fixed size blocks, growing adds a block and never moves what is already stored
+struct ChunkList {
+	chunks: Vec<NonNull<u8>>,
+	rows: usize,
 	size: usize,
 	align: usize,
 	initialized: bool,
 	_marker: PhantomData<u8>,
 }
-unsafe impl Send for RawVec {}
-unsafe impl Sync for RawVec {}
-impl RawVec {
+unsafe impl Send for ChunkList {}
+unsafe impl Sync for ChunkList {}
+impl ChunkList {
+	fn dangling(align: usize) -> NonNull<u8> {
+		// dangling is address 1, moved up to the alignment without ever pointing at anything
+		NonNull::new(NonNull::<u8>::dangling().as_ptr().wrapping_add(align - 1)).unwrap()
+	}
+	// zero sized values still get a finite row count, it paces the chunked tick column next to them
+	fn default_rows(size: usize) -> usize {
+		(CHUNK_BYTES / size.max(1)).max(1)
+	}
 	fn new_as<T:'static>() -> Self {
 		Self {
-			ptr: NonNull::dangling(),
-			cap: 0,
+			chunks: Vec::new(),
+			rows: Self::default_rows(mem::size_of::<T>()),
 			size: mem::size_of::<T>(),
 			align: mem::align_of::<T>(),
 			initialized: true,
@@ -23,8 +35,8 @@ impl RawVec {
 	}
 	fn imitate(&self) -> Self {
 		Self {
-			ptr: NonNull::dangling(),
-			cap: 0,
+			chunks: Vec::new(),
+			rows: self.rows,
 			size: self.size,
 			align: self.align,
 			initialized: self.initialized,
@@ -34,56 +46,88 @@ impl RawVec {
 	fn initialize<T:'static>(&mut self) {
 		self.size = mem::size_of::<T>();
 		self.align = mem::align_of::<T>();
+		self.rows = Self::default_rows(self.size);
 		self.initialized = true;
 	}
 	fn layout(&self) -> Layout {
 		assert!(self.initialized, "Access violation: uninitialized");
-		Layout::from_size_align(self.size * self.cap, self.align).unwrap()
+		Layout::from_size_align(self.size * self.rows, self.align).unwrap()
+	}
+	// zero sized columns never allocate, so they start (and stay) at full capacity
+	fn cap(&self) -> usize {
+		if self.size == 0 { usize::MAX } else { self.chunks.len() * self.rows }
 	}
 	fn grow(&mut self) {
-		assert!(self.initialized, "Access violation: uninitialized");
-		assert!(self.size != 0, "capacity overflow");
-
-		let new_cap = if self.cap == 0 { 1 } else { 2 * self.cap };
-		let new_layout = Layout::from_size_align(self.size * new_cap, self.align).unwrap();
-		assert!(new_layout.size() <= isize::MAX as usize, "Allocation too large");
-		let new_ptr = if self.cap == 0 {
-			unsafe { alloc::alloc(new_layout) }
+		let layout = self.layout();
+		assert!(layout.size() <= isize::MAX as usize, "Allocation too large");
+		let chunk = unsafe { alloc::alloc(layout) };
+		match NonNull::new(chunk) {
+			Some(p) => self.chunks.push(p),
+			None => alloc::handle_alloc_error(layout),
+		}
+	}
+	fn grow_to(&mut self, new_cap: usize) {
+		while self.cap() < new_cap {
+			self.grow();
+		}
+	}
+	// frees chunks past the first `keep`
+	fn release(&mut self, keep: usize) {
+		while self.chunks.len() > keep {
+			let chunk = self.chunks.pop().unwrap();
+			unsafe { alloc::dealloc(chunk.as_ptr(), self.layout()) };
+		}
+	}
+	fn slot(&self, index: usize) -> *mut u8 {
+		if self.size == 0 {
+			Self::dangling(self.align).as_ptr()
 		} else {
-			unsafe { alloc::realloc(self.ptr.as_ptr(), self.layout(), self.size * new_cap) }
-		};
-
-		self.ptr = match NonNull::new(new_ptr) {
-			Some(p) => p,
-			None => alloc::handle_alloc_error(new_layout),
-		};
-		self.cap = new_cap;
+			unsafe { self.chunks[index / self.rows].as_ptr().add((index % self.rows) * self.size) }
+		}
 	}
 }
-impl Drop for RawVec {
+impl Drop for ChunkList {
 	fn drop(&mut self) {
-		if self.cap != 0 {
-			let layout = Layout::from_size_align(self.size * self.cap, self.align).unwrap();
-			unsafe { alloc::dealloc(self.ptr.as_ptr(), layout); }
+		if self.size != 0 {
+			self.release(0);
 		}
 	}
 }
 
 
 pub struct ComponentVec {
-	buf: RawVec,
+	buf: ChunkList,
 	len: usize,
 	type_id: Option<TypeId>,
+	ticks: ChunkList,	// change tick per row, chunked in step with the values
+	newest: Vec<u32>,	// highest tick written into each tick chunk, so unchanged chunks can be skipped
+	latest: u32,	// highest tick written anywhere in the column
 }
 impl ComponentVec {
-	fn ptr_as<T:'static>(&self) -> *mut T {
-		self.buf.ptr.as_ptr().cast::<T>()
+	fn slot_as<T:'static>(&self, index: usize) -> *mut T {
+		self.buf.slot(index).cast::<T>()
 	}
-	fn ptr(&self) -> *mut u8 {
-		self.buf.ptr.as_ptr()
+	fn tick(&self, index: usize) -> *mut u32 {
+		self.ticks.slot(index).cast::<u32>()
 	}
-	fn cap(&self) -> usize {
-		self.buf.cap
+	unsafe fn set_tick(&mut self, index: usize, tick: u32) {
+		*self.tick(index) = tick;
+		let newest = &mut self.newest[index / self.ticks.rows];
+		*newest = (*newest).max(tick);
+		self.latest = self.latest.max(tick);
+	}
+	fn ticks_for(buf: &ChunkList) -> ChunkList {
+		let mut ticks = ChunkList::new_as::<u32>();
+		ticks.rows = buf.rows;
+		ticks
+	}
+	pub fn capacity(&self) -> usize {
+		self.buf.cap().min(self.ticks.cap())
+	}
+	fn grow_to(&mut self, new_cap: usize) {
+		self.buf.grow_to(new_cap);
+		self.ticks.grow_to(new_cap);
+		self.newest.resize(self.ticks.chunks.len(), 0);
 	}
 	pub fn len(&self) -> usize {
 		self.len
@@ -107,10 +151,14 @@ impl ComponentVec {
 		}
 	}
 	pub fn new_as<T:'static>() -> Self {
+		let buf = ChunkList::new_as::<T>();
 		Self {
-			buf: RawVec::new_as::<T>(),
+			ticks: Self::ticks_for(&buf),
+			buf,
 			len: 0,
 			type_id: Some(TypeId::of::<T>()),
+			newest: Vec::new(),
+			latest: 0,
 		}
 	}
 	pub fn from<T:'static, const N:usize>(data: [T;N]) -> Self {
@@ -123,14 +171,47 @@ impl ComponentVec {
 			buf: self.buf.imitate(),
 			len: 0,
 			type_id: self.type_id,
+			ticks: self.ticks.imitate(),
+			newest: Vec::new(),
+			latest: 0,
+		}
+	}
+	pub fn chunk_rows(&self) -> usize {
+		self.buf.rows
+	}
+	// the tick column allocates even for zero sized values, so it is the one to count
+	pub fn chunk_count(&self) -> usize {
+		self.ticks.chunks.len()
+	}
+	pub fn set_chunk_rows(&mut self, rows: usize) {
+		assert!(self.len == 0, "Cannot rechunk an occupied column");
+		assert!(rows > 0, "Chunks need room for at least one row");
+		if self.buf.size != 0 {
+			self.buf.release(0);
 		}
+		self.ticks.release(0);
+		self.newest.clear();
+		self.buf.rows = rows;
+		self.ticks.rows = rows;
+	}
+	// the only place chunks are given back, removing rows keeps them around like a Vec does
+	pub fn shrink_to_fit(&mut self) {
+		let used = self.len.div_ceil(self.ticks.rows);
+		if self.buf.size != 0 {
+			self.buf.release(used);
+		}
+		self.ticks.release(used);
+		self.newest.truncate(used);
 	}
 	pub fn push<T:'static>(&mut self, elem: T) {
 		assert!(self.is_type_or_set::<T>(), "Invalid type");
-		if !self.buf.initialized { self.buf.initialize::<T>(); }
-		if self.len == self.cap() { self.buf.grow(); }
+		if !self.buf.initialized { self.buf.initialize::<T>(); self.ticks.rows = self.buf.rows; }
+		self.grow_to(self.len + 1);
 
-		unsafe { ptr::write(self.ptr_as::<T>().add(self.len), elem) }
+		unsafe {
+			ptr::write(self.slot_as::<T>(self.len), elem);
+			*self.tick(self.len) = 0;
+		}
 		self.len += 1;
 	}
 	pub fn pop<T:'static>(&mut self) -> Option<T> {
@@ -139,235 +220,364 @@ impl ComponentVec {
 			None
 		} else {
 			self.len -= 1;
-			unsafe { Some(ptr::read(self.ptr_as::<T>().add(self.len))) }
+			unsafe { Some(ptr::read(self.slot_as::<T>(self.len))) }
 		}
 	}
 	pub fn insert<T:'static>(&mut self, index: usize, elem: T) {
 		assert!(self.is_type_or_set::<T>(), "Invalid type");
 		assert!(index < self.len, "index out of bounds");
-		if !self.buf.initialized { self.buf.initialize::<T>(); }
-		if self.cap() == self.len { self.buf.grow(); }
+		if !self.buf.initialized { self.buf.initialize::<T>(); self.ticks.rows = self.buf.rows; }
+		self.grow_to(self.len + 1);
 
 		unsafe {
-			ptr::copy(
-				self.ptr_as::<T>().add(index),
-				self.ptr_as::<T>().add(index + 1),
-				self.len - index,
-			);
-			ptr::write(self.ptr_as::<T>().add(index), elem);
-			self.len += 1;
+			// rows can straddle chunks, so shift one at a time
+			for i in (index..self.len).rev() {
+				ptr::copy_nonoverlapping(self.slot_as::<T>(i), self.slot_as::<T>(i + 1), 1);
+				self.set_tick(i + 1, *self.tick(i));
+			}
+			ptr::write(self.slot_as::<T>(index), elem);
+			*self.tick(index) = 0;
 		}
+		self.len += 1;
 	}
 	pub fn remove<T:'static>(&mut self, index: usize) -> T {
 		assert!(self.is_type::<T>(), "Invalid type");
 		assert!(index < self.len, "index out of bounds");
+		let result = unsafe { ptr::read(self.slot_as::<T>(index)) };
+		self.len -= 1;
 		unsafe {
-			self.len -= 1;
-			let result = ptr::read(self.ptr_as::<T>().add(index));
-			ptr::copy(
-				self.ptr_as::<T>().add(index + 1),
-				self.ptr_as::<T>().add(index),
-				self.len - index,
-			);
-			result
+			for i in index..self.len {
+				ptr::copy_nonoverlapping(self.slot_as::<T>(i + 1), self.slot_as::<T>(i), 1);
+				self.set_tick(i, *self.tick(i + 1));
+			}
 		}
+		result
 	}
 	pub fn swap_remove<T:'static>(&mut self, index: usize) -> T {
 		assert!(self.is_type::<T>(), "Invalid type");
 		assert!(index < self.len, "index out of bounds");
-		unsafe {
-			self.len -= 1;
-			if self.len > 0 {
-				ptr::swap(
-					self.ptr_as::<T>().add(index),
-					self.ptr_as::<T>().add(self.len)
-				);
-			}
-			ptr::read(self.ptr_as::<T>().add(self.len))
+		self.swap_to_tail(index);
+		self.len -= 1;
+		unsafe { ptr::read(self.slot_as::<T>(self.len)) }
+	}
+	// the tail takes the row's place and the removed value is dropped where it ended up
+	pub unsafe fn swap_drop(&mut self, index: usize, drop: Option<unsafe fn(*mut u8)>) {
+		self.swap_to_tail(index);
+		self.len -= 1;
+		if let Some(drop) = drop {
+			drop(self.buf.slot(self.len));
 		}
 	}
 	pub fn swap_forget(&mut self, index: usize) {
 		assert!(index < self.len, "index out of bounds");
-		unsafe {
-			self.len -= 1;
-			if self.len > 0 {
-				ptr::copy(
-					self.ptr().add(self.len * self.buf.size),
-					self.ptr().add(index * self.buf.size),
-					self.buf.size
-				);
+		self.len -= 1;
+		if index != self.len {
+			unsafe {
+				ptr::copy_nonoverlapping(self.buf.slot(self.len), self.buf.slot(index), self.buf.size);
+				self.set_tick(index, *self.tick(self.len));
 			}
 		}
 	}
-	pub fn as_slice<T:'static>(&self) -> &[T] {
+	pub fn get<T:'static>(&self, index: usize) -> Option<&T> {
 		assert!(self.is_type::<T>(), "Invalid type");
-		unsafe { std::slice::from_raw_parts(self.ptr_as::<T>(), self.len) }
+		if index < self.len {
+			unsafe { Some(&*self.slot_as::<T>(index)) }
+		} else {
+			None
+		}
 	}
-	pub fn as_mut_slice<T:'static>(&mut self) -> &mut [T] {
+	pub fn get_mut<T:'static>(&mut self, index: usize) -> Option<&mut T> {
 		assert!(self.is_type::<T>(), "Invalid type");
-		unsafe { std::slice::from_raw_parts_mut(self.ptr_as::<T>(), self.len) }
+		if index < self.len {
+			unsafe { Some(&mut *self.slot_as::<T>(index)) }
+		} else {
+			None
+		}
 	}
-	pub fn drain<T:'static>(&mut self) -> Drain<T> {
+	// the filled part of every chunk, in row order
+	pub fn chunks<T:'static>(&self) -> impl Iterator<Item = &[T]> {
 		assert!(self.is_type::<T>(), "Invalid type");
-		unsafe {
-			let iter = RawValIter::new(self.as_slice::<T>());
-			self.len = 0;
-			Drain {
-				iter,
-				vec: PhantomData,
+		let rows = self.buf.rows.min(self.len.max(1));
+		(0..self.len).step_by(rows).map(move |first| {
+			unsafe { std::slice::from_raw_parts(self.slot_as::<T>(first), rows.min(self.len - first)) }
+		})
+	}
+	pub fn chunks_mut<T:'static>(&mut self) -> impl Iterator<Item = &mut [T]> {
+		assert!(self.is_type::<T>(), "Invalid type");
+		let rows = self.buf.rows.min(self.len.max(1));
+		let (buf, len) = (&self.buf, self.len);
+		// each chunk is a separate allocation, so the slices never overlap
+		(0..len).step_by(rows).map(move |first| {
+			unsafe { std::slice::from_raw_parts_mut(buf.slot(first).cast::<T>(), rows.min(len - first)) }
+		})
+	}
+	pub fn iter<T:'static>(&self) -> impl Iterator<Item = &T> {
+		self.chunks::<T>().flatten()
+	}
+	pub fn iter_mut<T:'static>(&mut self) -> impl Iterator<Item = &mut T> {
+		self.chunks_mut::<T>().flatten()
+	}
+	pub fn drain<T:'static>(&mut self) -> Drain<'_, T> {
+		assert!(self.is_type::<T>(), "Invalid type");
+		// the column forgets its rows up front, whatever the iterator doesn't yield it drops
+		let end = self.len;
+		self.len = 0;
+		Drain { vec: self, next: 0, end, marker: PhantomData }
+	}
+	pub fn into_iter<T:'static>(mut self) -> IntoIter<T> {
+		assert!(self.is_type::<T>(), "Invalid type");
+		let end = self.len;
+		self.len = 0;
+		IntoIter { vec: self, next: 0, end, marker: PhantomData }
+	}
+	pub fn swap_to_tail(&mut self, index: usize) {
+		assert!(index < self.len, "index out of bounds");
+
+		if index != self.len - 1 {
+			unsafe {
+				ptr::swap_nonoverlapping(self.buf.slot(index), self.buf.slot(self.len - 1), self.buf.size);
+				let (moved, tail) = (*self.tick(self.len - 1), *self.tick(index));
+				self.set_tick(index, moved);
+				self.set_tick(self.len - 1, tail);
 			}
 		}
 	}
-	pub fn into_iter<T:'static>(self) -> IntoIter<T> {
-		assert!(self.is_type::<T>(), "Invalid type");
+	pub fn reserve(&mut self, additional: usize) {
+		self.grow_to(self.len + additional);
+	}
+	pub fn item_size(&self) -> usize {
+		self.buf.size
+	}
+	// the column's raw bytes, one slice per chunk
+	pub fn byte_chunks(&self) -> impl Iterator<Item = &[u8]> {
+		let rows = self.buf.rows.min(self.len.max(1));
+		(0..self.len).step_by(rows).map(move |first| {
+			unsafe { std::slice::from_raw_parts(self.buf.slot(first), rows.min(self.len - first) * self.buf.size) }
+		})
+	}
+	pub fn row_bytes(&self, index: usize) -> &[u8] {
+		unsafe { std::slice::from_raw_parts(self.get_ptr(index), self.buf.size) }
+	}
+	// caller guarantees `bytes` holds `count` valid values of the column's type
+	pub unsafe fn extend_from_bytes(&mut self, bytes: &[u8], count: usize) {
+		assert!(bytes.len() == count * self.buf.size, "Byte length doesn't match column layout");
+		self.reserve(count);
+		if self.buf.size != 0 {
+			let mut written = 0;
+			while written < count {
+				let room = self.buf.rows - (self.len + written) % self.buf.rows;
+				let n = room.min(count - written);
+				ptr::copy_nonoverlapping(
+					bytes.as_ptr().add(written * self.buf.size),
+					self.buf.slot(self.len + written),
+					n * self.buf.size,
+				);
+				written += n;
+			}
+		}
+		for i in self.len..self.len + count {
+			*self.tick(i) = 0;
+		}
+		self.len += count;
+	}
+	pub fn changed_tick(&self, index: usize) -> u32 {
+		assert!(index < self.len, "index out of bounds");
+		unsafe { *self.tick(index) }
+	}
+	pub fn mark_changed(&mut self, index: usize, tick: u32) {
+		assert!(index < self.len, "index out of bounds");
+		unsafe { self.set_tick(index, tick) };
+	}
+	pub fn mark_all_changed(&mut self, tick: u32) {
+		let rows = self.ticks.rows;
+		for first in (0..self.len).step_by(rows) {
+			unsafe { std::slice::from_raw_parts_mut(self.tick(first), rows.min(self.len - first)).fill(tick) };
+			let newest = &mut self.newest[first / rows];
+			*newest = (*newest).max(tick);
+		}
+		self.latest = self.latest.max(tick);
+	}
+	// rows whose tick is newer than `tick`, chunks nothing was written to since are skipped whole
+	pub fn changed_since(&self, tick: u32) -> impl Iterator<Item = usize> + '_ {
+		let rows = self.ticks.rows;
+		let newest = if self.latest > tick { self.newest.as_slice() } else { &[] };
+		newest.iter().enumerate()
+			.filter(move |(_, newest)| **newest > tick)
+			.flat_map(move |(chunk, _)| chunk * rows..((chunk + 1) * rows).min(self.len))
+			.filter(move |row| unsafe { *self.tick(*row) } > tick)
+	}
+	// bitwise copy of one row, the caller decides which side still owns the value
+	pub unsafe fn copy_row(&self, index: usize) -> Self {
+		let mut cv = self.imitate();
+		cv.extend_from_bytes(self.row_bytes(index), 1);
+		cv
+	}
+	pub fn get_ptr(&self, index: usize) -> *mut u8 {
+		assert!(index < self.len, "index out of bounds");
+		self.buf.slot(index)
+	}
+	// bitwise copy, only sound for types that are Copy
+	pub unsafe fn duplicate(&self) -> Self {
+		let mut cv = self.imitate();
+		self.extend_into(&mut cv);
+		cv
+	}
+	// drops the rows from `len` on
+	pub unsafe fn truncate(&mut self, len: usize, drop: Option<unsafe fn(*mut u8)>) {
+		while self.len > len {
+			self.len -= 1;
+			if let Some(drop) = drop {
+				drop(self.buf.slot(self.len));
+			}
+		}
+	}
+	pub unsafe fn drop_elements(&mut self, drop: Option<unsafe fn(*mut u8)>) {
+		if let Some(drop) = drop {
+			for i in 0..self.len {
+				drop(self.buf.slot(i));
+			}
+		}
+		self.len = 0;
+	}
+	pub fn append(&mut self, src: &mut Self) {
+		assert!(self.type_id == src.type_id, "Incompatible types");
+		let first = self.len;
 		unsafe {
-			let iter = RawValIter::new(self.as_slice::<T>());
-			let buf = ptr::read(&self.buf);
-			mem::forget(self);
-
-			IntoIter {
-				iter,
-				_buf: buf
+			src.extend_into(self);
+			for i in 0..src.len {
+				self.set_tick(first + i, *src.tick(i));
 			}
 		}
+		src.len = 0;
 	}
-	pub fn swap_to_tail(&mut self, index: usize) {
+	unsafe fn extend_into(&self, dst: &mut Self) {
+		if self.buf.size == 0 {
+			dst.extend_from_bytes(&[], self.len);
+			return;
+		}
+		dst.reserve(self.len);
+		for bytes in self.byte_chunks() {
+			dst.extend_from_bytes(bytes, bytes.len() / self.buf.size);
+		}
+	}
+	pub fn replace_with_tail(&mut self, index: usize, src: &mut Self) {
+		assert!(self.type_id == src.type_id, "Incompatible types");
 		assert!(index < self.len, "index out of bounds");
-		
-		if index != self.len - 1 {
-			for i in 0..self.buf.size {
-				unsafe {ptr::swap(
-					self.ptr().add(index * self.buf.size).add(i),
-					self.ptr().add(self.len * self.buf.size).add(i),
-				)};
+		if src.len > 0 {
+			src.len -= 1;
+			unsafe {
+				ptr::copy_nonoverlapping(src.buf.slot(src.len), self.buf.slot(index), src.buf.size);
+				self.set_tick(index, *src.tick(src.len));
 			}
 		}
 	}
 	pub fn adopt_tail(&mut self, src: &mut Self) {
 		assert!(self.type_id == src.type_id, "Incompatible types");
 		if src.len > 0 {
-			if self.len == self.cap() { self.buf.grow(); }
+			self.grow_to(self.len + 1);
+			src.len -= 1;
 			unsafe {
-				src.len -= 1;
-				ptr::copy_nonoverlapping(
-					src.ptr().add(src.len * src.buf.size), 
-					self.ptr().add(self.len * self.buf.size), 
-					src.buf.size
-				);
-				self.len += 1;
+				ptr::copy_nonoverlapping(src.buf.slot(src.len), self.buf.slot(self.len), src.buf.size);
+				self.set_tick(self.len, *src.tick(src.len));
 			}
+			self.len += 1;
 		}
 	}
 }
 
 
-struct RawValIter<T> {
-	start: *const T,
-	end: *const T,
+// a detached column that drops whatever it still holds
+pub(crate) struct OwnedColumn {
+	pub data: ComponentVec,
+	pub drop: Option<unsafe fn(*mut u8)>,
 }
-impl<T> RawValIter<T> {
-	unsafe fn new(slice: &[T]) -> Self {
-		Self {
-			start: slice.as_ptr(),
-			end: if mem::size_of::<T>() == 0 {
-				((slice.as_ptr() as usize) + slice.len()) as *const _
-			} else if slice.len() == 0 {
-				slice.as_ptr()
-			} else {
-				slice.as_ptr().add(slice.len())
-			},
-		}
+impl OwnedColumn {
+	// hands the values over to a caller that takes ownership of them
+	pub fn into_inner(self) -> ComponentVec {
+		let this = mem::ManuallyDrop::new(self);
+		unsafe { ptr::read(&this.data) }
 	}
 }
-impl<T> Iterator for RawValIter<T> {
-	type Item = T;
-	fn next(&mut self) -> Option<T> {
-		if self.start == self.end {
-			None
-		} else {
-			unsafe {
-				if mem::size_of::<T>() == 0 {
-					self.start = (self.start as usize + 1) as *const _;
-					Some(ptr::read(NonNull::<T>::dangling().as_ptr()))
-				} else {
-					let old_ptr = self.start;
-					self.start = self.start.offset(1);
-					Some(ptr::read(old_ptr))
-				}
-			}
-		}
-	}
-	fn size_hint(&self) -> (usize, Option<usize>) {
-		let elem_size = mem::size_of::<T>();
-		let len = (self.end as usize - self.start as usize)
-				/ if elem_size == 0 { 1 } else { elem_size };
-		(len, Some(len))
-	}
-}
-impl<T> DoubleEndedIterator for RawValIter<T> {
-	fn next_back(&mut self) -> Option<Self::Item> {
-		if self.start == self.end {
-			None
-		} else {
-			unsafe {
-				if mem::size_of::<T>() == 0 {
-					self.end = (self.end as usize - 1) as *const _;
-					Some(ptr::read(NonNull::<T>::dangling().as_ptr()))
-				} else {
-					self.end = self.end.offset(-1);
-					Some(ptr::read(self.end))
-				}
-			}
-		}
+impl Drop for OwnedColumn {
+	fn drop(&mut self) {
+		unsafe { self.data.drop_elements(self.drop) };
 	}
 }
 
 
 pub struct IntoIter<T> {
-	_buf: RawVec,
-	iter: RawValIter<T>
+	vec: ComponentVec,
+	next: usize,
+	end: usize,
+	marker: PhantomData<T>,
 }
-impl<T> Iterator for IntoIter<T> {
+impl<T:'static> Iterator for IntoIter<T> {
 	type Item = T;
 	fn next(&mut self) -> Option<Self::Item> {
-		self.iter.next()
+		if self.next == self.end {
+			None
+		} else {
+			self.next += 1;
+			unsafe { Some(ptr::read(self.vec.slot_as::<T>(self.next - 1))) }
+		}
 	}
 	fn size_hint(&self) -> (usize, Option<usize>) {
-		self.iter.size_hint()
+		(self.end - self.next, Some(self.end - self.next))
 	}
 }
-impl<T> DoubleEndedIterator for IntoIter<T> {
+impl<T:'static> DoubleEndedIterator for IntoIter<T> {
 	fn next_back(&mut self) -> Option<Self::Item> {
-		self.iter.next_back()
+		if self.next == self.end {
+			None
+		} else {
+			self.end -= 1;
+			unsafe { Some(ptr::read(self.vec.slot_as::<T>(self.end))) }
+		}
 	}
 }
 impl<T> Drop for IntoIter<T> {
 	fn drop(&mut self) {
-		for _ in &mut *self {}
+		for i in self.next..self.end {
+			unsafe { ptr::drop_in_place(self.vec.buf.slot(i).cast::<T>()) };
+		}
 	}
 }
 
 
 pub struct Drain<'a, T: 'a> {
-	vec: PhantomData<&'a mut ComponentVec>,
-	iter: RawValIter<T>,
+	vec: &'a mut ComponentVec,
+	next: usize,
+	end: usize,
+	marker: PhantomData<T>,
 }
-impl<'a, T> Iterator for Drain<'a, T> {
+impl<'a, T:'static> Iterator for Drain<'a, T> {
 	type Item = T;
 	fn next(&mut self) -> Option<Self::Item> {
-		self.iter.next()
+		if self.next == self.end {
+			None
+		} else {
+			self.next += 1;
+			unsafe { Some(ptr::read(self.vec.slot_as::<T>(self.next - 1))) }
+		}
 	}
 	fn size_hint(&self) -> (usize, Option<usize>) {
-		self.iter.size_hint()
+		(self.end - self.next, Some(self.end - self.next))
 	}
 }
-impl<'a, T> DoubleEndedIterator for Drain<'a, T> {
+impl<'a, T:'static> DoubleEndedIterator for Drain<'a, T> {
 	fn next_back(&mut self) -> Option<Self::Item> {
-		self.iter.next_back()
+		if self.next == self.end {
+			None
+		} else {
+			self.end -= 1;
+			unsafe { Some(ptr::read(self.vec.slot_as::<T>(self.end))) }
+		}
 	}
 }
 impl<'a, T> Drop for Drain<'a, T> {
 	fn drop(&mut self) {
-		for _ in &mut *self {}
+		for i in self.next..self.end {
+			unsafe { ptr::drop_in_place(self.vec.buf.slot(i).cast::<T>()) };
+		}
 	}
-}
\ No newline at end of file
+}
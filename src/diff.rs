@@ -0,0 +1,146 @@
+use std::{any::TypeId, fmt};
+
+use hashbrown::{HashMap, HashSet};
+
+use crate::{component_vec::ComponentVec, ComponentRegistry, EntityId, Snapshot, ECS};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SignatureDiff {
+	pub entity: EntityId,
+	pub left: Vec<String>,
+	pub right: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ValueDiff {
+	pub entity: EntityId,
+	pub component: String,
+	pub left: String,
+	pub right: String,
+}
+
+// values are only compared for components registered with `with_eq`, and shown through `with_debug`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct WorldDiff {
+	pub only_left: Vec<EntityId>,
+	pub only_right: Vec<EntityId>,
+	pub signatures: Vec<SignatureDiff>,
+	pub values: Vec<ValueDiff>,
+}
+impl WorldDiff {
+	pub fn is_empty(&self) -> bool {
+		self.only_left.is_empty() && self.only_right.is_empty() && self.signatures.is_empty() && self.values.is_empty()
+	}
+	pub fn len(&self) -> usize {
+		self.only_left.len() + self.only_right.len() + self.signatures.len() + self.values.len()
+	}
+}
+impl fmt::Display for WorldDiff {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		if self.is_empty() {
+			return writeln!(f, "worlds are identical");
+		}
+		writeln!(f, "{} difference(s)", self.len())?;
+		for e in self.only_left.iter() {
+			writeln!(f, "  {} only on the left", e.0)?;
+		}
+		for e in self.only_right.iter() {
+			writeln!(f, "  {} only on the right", e.0)?;
+		}
+		for d in self.signatures.iter() {
+			writeln!(f, "  {} has [{}] vs [{}]", d.entity.0, d.left.join(", "), d.right.join(", "))?;
+		}
+		for d in self.values.iter() {
+			writeln!(f, "  {} {}: {} vs {}", d.entity.0, d.component, d.left, d.right)?;
+		}
+		Ok(())
+	}
+}
+
+// every entity with the rows it owns, whichever storage they came from
+type Side<'a> = HashMap<EntityId, Vec<(TypeId, &'a ComponentVec, usize)>>;
+
+fn world_side<'a>(ecs: &'a ECS, only: Option<&HashSet<TypeId>>) -> Side<'a> {
+	let mut side: Side = HashMap::with_capacity(ecs.entity_count());
+	for (_, arche) in ecs.archetypes.iter() {
+		let columns: Vec<_> = arche.types()
+			.filter(|tid| **tid != TypeId::of::<EntityId>() && only.is_none_or(|only| only.contains(*tid)))
+			.map(|tid| (*tid, arche.get_column(tid).unwrap()))
+			.collect();
+		for (row, e) in arche.get_component_vec::<EntityId>().enumerate() {
+			side.insert(*e, columns.iter().map(|(tid, cv)| (*tid, *cv, row)).collect());
+		}
+	}
+	for (tid, set) in ecs.sparse.iter().filter(|(tid, _)| only.is_none_or(|only| only.contains(*tid))) {
+		for (row, e) in set.entities().iter().enumerate() {
+			if let Some(rows) = side.get_mut(e) {
+				rows.push((*tid, set.column(), row));
+			}
+		}
+	}
+	side
+}
+
+fn snapshot_side(snapshot: &Snapshot) -> Side<'_> {
+	let mut side: Side = snapshot.alive.iter().map(|e| (*e, Vec::new())).collect();
+	for table in snapshot.tables.iter() {
+		for (row, e) in table.entities.iter().enumerate() {
+			side.insert(*e, table.columns.iter().map(|col| (col.data.type_id(), &col.data, row)).collect());
+		}
+	}
+	for set in snapshot.sparse.iter() {
+		for (row, e) in set.entities.iter().enumerate() {
+			side.entry(*e).or_default().push((set.column.data.type_id(), &set.column.data, row));
+		}
+	}
+	side
+}
+
+fn compare(left: &Side, right: &Side, registry: &ComponentRegistry) -> WorldDiff {
+	let name = |tid: &TypeId| registry.get(tid).map_or_else(|| format!("{:?}", tid), |info| info.name().to_string());
+	let mut diff = WorldDiff::default();
+	for (e, l_rows) in left.iter() {
+		let Some(r_rows) = right.get(e) else {
+			diff.only_left.push(*e);
+			continue;
+		};
+		let mut l_types: Vec<String> = l_rows.iter().map(|(tid, ..)| name(tid)).collect();
+		let mut r_types: Vec<String> = r_rows.iter().map(|(tid, ..)| name(tid)).collect();
+		l_types.sort();
+		r_types.sort();
+		if l_types != r_types {
+			diff.signatures.push(SignatureDiff { entity: *e, left: l_types, right: r_types });
+		}
+		for (tid, l_cv, l_row) in l_rows.iter() {
+			let (Some((_, r_cv, r_row)), Some(info)) = (r_rows.iter().find(|(t, ..)| t == tid), registry.get(tid)) else {
+				continue;
+			};
+			if info.eq(l_cv, *l_row, r_cv, *r_row) == Some(false) {
+				let show = |cv, row| info.debug(cv, row).unwrap_or_else(|| "<no debug>".to_string());
+				diff.values.push(ValueDiff {
+					entity: *e,
+					component: info.name().to_string(),
+					left: show(l_cv, *l_row),
+					right: show(r_cv, *r_row),
+				});
+			}
+		}
+	}
+	diff.only_right.extend(right.keys().filter(|e| !left.contains_key(*e)));
+	diff.only_left.sort_by_key(|e| e.0);
+	diff.only_right.sort_by_key(|e| e.0);
+	diff.signatures.sort_by_key(|d| d.entity.0);
+	diff.values.sort_by(|a, b| (a.entity.0, &a.component).cmp(&(b.entity.0, &b.component)));
+	diff
+}
+
+impl ECS {
+	pub fn diff(&self, other: &ECS) -> WorldDiff {
+		compare(&world_side(self, None), &world_side(other, None), &self.registry)
+	}
+	// a snapshot only holds rollback components, so only those take part
+	pub fn diff_snapshot(&self, snapshot: &Snapshot) -> WorldDiff {
+		let rollback = self.rollback_types();
+		compare(&world_side(self, Some(&rollback)), &snapshot_side(snapshot), &self.registry)
+	}
+}
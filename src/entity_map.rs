@@ -0,0 +1,58 @@
+use hashbrown::HashMap;
+
+use crate::EntityId;
+
+pub trait MapEntities {
+	fn map_entities(&mut self, map: &EntityMap);
+}
+impl MapEntities for EntityId {
+	fn map_entities(&mut self, map: &EntityMap) {
+		*self = map.map(*self);
+	}
+}
+impl<T: MapEntities> MapEntities for Option<T> {
+	fn map_entities(&mut self, map: &EntityMap) {
+		if let Some(inner) = self {
+			inner.map_entities(map);
+		}
+	}
+}
+impl<T: MapEntities> MapEntities for Vec<T> {
+	fn map_entities(&mut self, map: &EntityMap) {
+		for inner in self.iter_mut() {
+			inner.map_entities(map);
+		}
+	}
+}
+
+#[derive(Default, Clone)]
+pub struct EntityMap {
+	map: HashMap<EntityId, EntityId>,
+}
+impl EntityMap {
+	pub fn new() -> Self {
+		Self::default()
+	}
+	pub fn insert(&mut self, from: EntityId, to: EntityId) -> Option<EntityId> {
+		self.map.insert(from, to)
+	}
+	pub fn remove(&mut self, from: &EntityId) -> Option<EntityId> {
+		self.map.remove(from)
+	}
+	pub fn get(&self, from: &EntityId) -> Option<EntityId> {
+		self.map.get(from).copied()
+	}
+	// unmapped ids are left as-is so references outside the mapped set survive
+	pub fn map(&self, from: EntityId) -> EntityId {
+		self.get(&from).unwrap_or(from)
+	}
+	pub fn len(&self) -> usize {
+		self.map.len()
+	}
+	pub fn is_empty(&self) -> bool {
+		self.map.is_empty()
+	}
+	pub fn iter(&self) -> impl Iterator<Item = (&EntityId, &EntityId)> {
+		self.map.iter()
+	}
+}
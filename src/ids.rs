@@ -0,0 +1,34 @@
+use uuid::Uuid;
+
+// hands out entity and archetype ids, either random or reproducible from a seed
+#[derive(Clone)]
+pub(crate) enum IdAllocator {
+	Random,
+	Seeded { seed: u64, next: u64 },
+}
+impl IdAllocator {
+	pub fn seeded(seed: u64) -> Self {
+		IdAllocator::Seeded { seed, next: 0 }
+	}
+	pub fn is_seeded(&self) -> bool {
+		matches!(self, IdAllocator::Seeded { .. })
+	}
+	// an id from this seed that was handed out somewhere else is never handed out again here
+	pub fn skip_past(&mut self, id: Uuid) {
+		if let IdAllocator::Seeded { seed, next } = self {
+			let (high, low) = id.as_u64_pair();
+			if high == *seed {
+				*next = (*next).max(low);
+			}
+		}
+	}
+	pub fn next(&mut self) -> Uuid {
+		match self {
+			IdAllocator::Random => Uuid::new_v4(),
+			IdAllocator::Seeded { seed, next } => {
+				*next += 1;
+				Uuid::from_u64_pair(*seed, *next)
+			},
+		}
+	}
+}
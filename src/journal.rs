@@ -0,0 +1,225 @@
+use std::{any::TypeId, fmt, fs, io, path::Path};
+
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+
+use crate::{EntityId, StableId, WorldRng, ECS};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum JournalOp {
+	Create { entity: EntityId },
+	Attach { entity: EntityId, component: StableId, value: Value },
+	Set { entity: EntityId, component: StableId, value: Value },
+	Detach { entity: EntityId, component: StableId },
+	Destroy { entity: EntityId },
+}
+
+// components without a serde registration never make it into the journal
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Journal {
+	pub rng: WorldRng,
+	pub ops: Vec<JournalOp>,
+	#[serde(skip)]
+	since: u32,
+}
+impl Journal {
+	pub fn len(&self) -> usize {
+		self.ops.len()
+	}
+	pub fn is_empty(&self) -> bool {
+		self.ops.is_empty()
+	}
+	pub fn to_json(&self) -> Result<String, JournalError> {
+		Ok(serde_json::to_string_pretty(self)?)
+	}
+	pub fn from_json(json: &str) -> Result<Self, JournalError> {
+		Ok(serde_json::from_str(json)?)
+	}
+	pub fn save(&self, path: impl AsRef<Path>) -> Result<(), JournalError> {
+		Ok(fs::write(path, self.to_json()?)?)
+	}
+	pub fn load(path: impl AsRef<Path>) -> Result<Self, JournalError> {
+		Self::from_json(&fs::read_to_string(path)?)
+	}
+	pub fn replay(&self, ecs: &mut ECS) -> Result<(), JournalError> {
+		let mut replay = self.replayer();
+		while replay.step(ecs)?.is_some() {}
+		Ok(())
+	}
+	pub fn replayer(&self) -> Replay<'_> {
+		Replay { journal: self, cursor: 0 }
+	}
+}
+
+#[derive(Debug)]
+pub enum JournalError {
+	UnknownComponent(StableId),
+	UnknownEntity(EntityId),
+	DuplicateEntity(EntityId),
+	Serde(serde_json::Error),
+	Io(io::Error),
+}
+impl fmt::Display for JournalError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			JournalError::UnknownComponent(id) => write!(f, "no serializable component registered as {:?}", id),
+			JournalError::UnknownEntity(e) => write!(f, "entity {:?} does not exist", e),
+			JournalError::DuplicateEntity(e) => write!(f, "entity {:?} already exists", e),
+			JournalError::Serde(e) => write!(f, "{}", e),
+			JournalError::Io(e) => write!(f, "{}", e),
+		}
+	}
+}
+impl std::error::Error for JournalError {}
+impl From<serde_json::Error> for JournalError {
+	fn from(e: serde_json::Error) -> Self {
+		JournalError::Serde(e)
+	}
+}
+impl From<io::Error> for JournalError {
+	fn from(e: io::Error) -> Self {
+		JournalError::Io(e)
+	}
+}
+
+// steps through a journal one op at a time, the world can be inspected between steps
+pub struct Replay<'a> {
+	journal: &'a Journal,
+	cursor: usize,
+}
+impl<'a> Replay<'a> {
+	pub fn position(&self) -> usize {
+		self.cursor
+	}
+	pub fn is_finished(&self) -> bool {
+		self.cursor == self.journal.ops.len()
+	}
+	pub fn peek(&self) -> Option<&'a JournalOp> {
+		self.journal.ops.get(self.cursor)
+	}
+	pub fn step(&mut self, ecs: &mut ECS) -> Result<Option<&'a JournalOp>, JournalError> {
+		if self.cursor == 0 {
+			ecs.rng = self.journal.rng.clone();
+		}
+		let Some(op) = self.journal.ops.get(self.cursor) else {
+			return Ok(None);
+		};
+		ecs.apply_journal_op(op)?;
+		self.cursor += 1;
+		Ok(Some(op))
+	}
+	pub fn run_to(&mut self, ecs: &mut ECS, position: usize) -> Result<(), JournalError> {
+		while self.cursor < position.min(self.journal.ops.len()) {
+			self.step(ecs)?;
+		}
+		Ok(())
+	}
+}
+
+impl ECS {
+	pub fn start_journal(&mut self) {
+		self.journal = Some(Journal { rng: self.rng.clone(), ops: Vec::new(), since: self.change_tick });
+		self.advance_tick();
+	}
+	pub fn is_journaling(&self) -> bool {
+		self.journal.is_some()
+	}
+	pub fn journal(&self) -> Option<&Journal> {
+		self.journal.as_ref()
+	}
+	pub fn stop_journal(&mut self) -> Option<Journal> {
+		self.record_changes();
+		self.journal.take()
+	}
+	// mutations through references can't be seen as they happen, so they are swept up from change ticks.
+	// every journaled operation sweeps first, which keeps them ahead of the ops that followed them
+	pub fn record_changes(&mut self) {
+		let Some(journal) = self.journal.as_mut() else {
+			return;
+		};
+		for (_, arche) in self.archetypes.iter() {
+			let mut infos: Vec<_> = arche.types()
+				.filter(|tid| **tid != TypeId::of::<EntityId>())
+				.filter_map(|tid| self.registry.get(tid))
+				.filter(|info| info.has_serde())
+				.collect();
+			infos.sort_by_key(|info| info.stable_id());
+			// only rows written since the last sweep are visited, not the whole world
+			let mut rows: Vec<usize> = infos.iter()
+				.flat_map(|info| arche.get_column(&info.type_id()).unwrap().changed_since(journal.since))
+				.collect();
+			rows.sort_unstable();
+			rows.dedup();
+			for row in rows {
+				let entity = arche.get_component::<EntityId>(row).unwrap();
+				for info in infos.iter() {
+					let cv = arche.get_column(&info.type_id()).unwrap();
+					if cv.changed_tick(row) > journal.since {
+						if let Ok(value) = info.serialize(cv, row).unwrap() {
+							journal.ops.push(JournalOp::Set { entity: *entity, component: info.stable_id(), value });
+						}
+					}
+				}
+			}
+		}
+		journal.since = self.change_tick;
+		self.advance_tick();
+	}
+	// rows the op itself wrote count as recorded, so they don't come back as a Set on the next sweep
+	pub(crate) fn journal_push(&mut self, op: JournalOp) {
+		if let Some(journal) = self.journal.as_mut() {
+			journal.ops.push(op);
+			journal.since = self.change_tick;
+			self.advance_tick();
+		}
+	}
+	pub(crate) fn journal_attach(&mut self, entity: EntityId, tid: &TypeId) {
+		if !self.is_journaling() {
+			return;
+		}
+		let (Some(info), Some((cv, row))) = (self.registry.get(tid), self.column_of(entity, tid)) else {
+			return;
+		};
+		if let Some(Ok(value)) = info.serialize(cv, row) {
+			self.journal_push(JournalOp::Attach { entity, component: info.stable_id(), value });
+		}
+	}
+	pub(crate) fn journal_detach(&mut self, entity: EntityId, tid: &TypeId) {
+		if !self.is_journaling() || self.column_of(entity, tid).is_none() {
+			return;
+		}
+		if let Some(info) = self.registry.get(tid).filter(|info| info.has_serde()) {
+			self.journal_push(JournalOp::Detach { entity, component: info.stable_id() });
+		}
+	}
+	fn apply_journal_op(&mut self, op: &JournalOp) -> Result<(), JournalError> {
+		let require = |ecs: &ECS, entity: &EntityId| if ecs.contains_entity(*entity) { Ok(()) } else { Err(JournalError::UnknownEntity(*entity)) };
+		match op {
+			JournalOp::Create { entity } => {
+				if self.contains_entity(*entity) {
+					return Err(JournalError::DuplicateEntity(*entity));
+				}
+				self.spawn_with_id(*entity);
+			},
+			JournalOp::Attach { entity, component, value } | JournalOp::Set { entity, component, value } => {
+				require(self, entity)?;
+				let info = self.registry.get_by_stable(*component)
+					.filter(|info| info.has_serde())
+					.ok_or(JournalError::UnknownComponent(*component))?;
+				let mut column = info.new_column();
+				info.deserialize_into(value.clone(), &mut column).unwrap()?;
+				self.attach_column(*entity, &mut column);
+			},
+			JournalOp::Detach { entity, component } => {
+				require(self, entity)?;
+				let tid = self.registry.get_by_stable(*component).ok_or(JournalError::UnknownComponent(*component))?.type_id();
+				self.remove_type(*entity, &tid);
+			},
+			JournalOp::Destroy { entity } => {
+				require(self, entity)?;
+				self.despawn(*entity);
+			},
+		}
+		Ok(())
+	}
+}
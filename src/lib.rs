@@ -1,36 +1,132 @@
 mod component_vec;
 mod archetype;
 mod type_graph;
+mod registry;
+mod entity_map;
+mod scene;
+mod binary;
+mod rollback;
+mod checksum;
+mod ids;
+mod rng;
+mod replication;
+mod relevance;
+mod journal;
+mod undo;
+mod transaction;
+mod diff;
+mod sparse;
+mod shared;
+mod multi;
+mod bundle;
+mod bulk;
 #[cfg(test)]
+#[allow(dead_code, clippy::approx_constant, clippy::assertions_on_constants)]
 mod tests;
 
 use std::any::TypeId;
 use hashbrown::{HashMap, HashSet};
 use uuid::Uuid;
+use serde::{Serialize, Deserialize};
 use type_graph::TypeGraph;
+use ids::IdAllocator;
+use undo::History;
+use sparse::SparseSet;
+use component_vec::ComponentVec;
+use bundle::BatchGuard;
+pub use registry::{ComponentRegistry, ComponentInfo, StableId, JsonMigration, BinaryMigration, MigrationError};
+pub use entity_map::{EntityMap, MapEntities};
+pub use scene::{Scene, SceneArchetype, SceneEntity, SceneError};
+pub use binary::{Pod, BinaryCodec, BinaryReader, BinaryError};
+pub use rollback::{Snapshot, RollbackBuffer};
+pub use checksum::{StableHash, StableHasher, WorldChecksum};
+pub use rng::{WorldRng, RngStream};
+pub use replication::{Delta, Replicator, Replica, ReplicationError};
+pub use relevance::{Relevance, Everything, VisibilitySet, Distance, SameTeam, AnyOf, AllOf, RelevanceEvent};
+pub use journal::{Journal, JournalOp, JournalError, Replay};
+pub use transaction::Transaction;
+pub use diff::{WorldDiff, SignatureDiff, ValueDiff};
+pub use shared::Shared;
+pub use multi::{Multi, InstanceKey};
+pub use bundle::Bundle;
 
-#[derive(Hash, PartialEq, Eq, Clone, Copy)]
+#[derive(Hash, PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct EntityId(Uuid);
 
-#[derive(Hash, PartialEq, Eq, Clone, Copy)]
+#[derive(Hash, PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct ArchetypeId(Uuid);
 
 pub struct ECS {
 	entity_index: HashMap<EntityId, (ArchetypeId, usize)>,
 	component_index: HashMap<TypeId, HashSet<ArchetypeId>>,
 	archetypes: TypeGraph,
+	registry: ComponentRegistry,
+	entity_ids: IdAllocator,
+	rng: WorldRng,
+	change_tick: u32,
+	journal: Option<Journal>,
+	history: History,
+	sparse: HashMap<TypeId, SparseSet>,
+	shared: HashMap<TypeId, Box<dyn shared::AnyPool>>,
+	archetype_limit: Option<usize>,
+}
+impl Default for ECS {
+	fn default() -> Self {
+		Self::new()
+	}
 }
 impl ECS {
 	pub fn new() -> Self {
-		let archetypes = TypeGraph::new();
+		Self::with_ids(IdAllocator::Random, IdAllocator::Random, WorldRng::from_entropy())
+	}
+	// entity and archetype ids are drawn from the seed, so identical operations yield identical worlds
+	pub fn with_seed(seed: u64) -> Self {
+		Self::with_ids(IdAllocator::seeded(seed), IdAllocator::seeded(seed), WorldRng::new(seed))
+	}
+	fn with_ids(entity_ids: IdAllocator, archetype_ids: IdAllocator, rng: WorldRng) -> Self {
+		let archetypes = TypeGraph::new(archetype_ids);
 		let mut component_index = HashMap::new();
 		component_index.insert(TypeId::of::<EntityId>(), HashSet::from([archetypes.root()]));
+		let mut registry = ComponentRegistry::new();
+		registry.register_stable::<EntityId>("aecs::EntityId").with_serde::<EntityId>().with_map_entities::<EntityId>().with_pod::<EntityId>();
 		Self {
 			entity_index: HashMap::new(),
 			component_index,
 			archetypes,
+			registry,
+			entity_ids,
+			rng,
+			change_tick: 1,
+			journal: None,
+			history: History::default(),
+			sparse: HashMap::new(),
+			shared: HashMap::new(),
+			archetype_limit: None,
 		}
 	}
+	pub fn rng(&self) -> &WorldRng {
+		&self.rng
+	}
+	pub fn rng_mut(&mut self) -> &mut WorldRng {
+		&mut self.rng
+	}
+	pub fn is_deterministic(&self) -> bool {
+		self.entity_ids.is_seeded()
+	}
+	// rows written through the world are stamped with the current tick
+	pub fn change_tick(&self) -> u32 {
+		self.change_tick
+	}
+	pub fn advance_tick(&mut self) -> u32 {
+		self.change_tick += 1;
+		self.change_tick
+	}
+	pub fn registry(&self) -> &ComponentRegistry {
+		&self.registry
+	}
+	pub fn registry_mut(&mut self) -> &mut ComponentRegistry {
+		&mut self.registry
+	}
 	pub fn archetypes(&self) -> &TypeGraph {
 		&self.archetypes
 	}
@@ -38,60 +134,259 @@ impl ECS {
 		&mut self.archetypes
 	}
 	pub fn create_entity(&mut self) -> EntityId {
-		let eid = EntityId(Uuid::new_v4());
+		self.record_changes();
+		let eid = EntityId(self.entity_ids.next());
+		self.spawn_with_id(eid);
+		self.journal_push(JournalOp::Create { entity: eid });
+		self.undo_spawn(eid);
+		eid
+	}
+	// ids that come from outside still move a seeded allocator along
+	fn spawn_with_id(&mut self, eid: EntityId) {
+		self.entity_ids.skip_past(eid.0);
 		let row = push_entity!(self.archetypes.get_mut(&self.archetypes.root()).unwrap(), [eid]);
 		self.entity_index.insert(eid, (self.archetypes.root(), row));
-		eid
+	}
+	pub fn contains_entity(&self, entity: EntityId) -> bool {
+		self.entity_index.contains_key(&entity)
+	}
+	pub fn entity_count(&self) -> usize {
+		self.entity_index.len()
+	}
+	// archetypes in creation order, rows in storage order
+	pub fn entities(&self) -> impl Iterator<Item = EntityId> + '_ {
+		self.archetypes.iter().flat_map(|(_, arche)| arche.get_component_vec::<EntityId>().copied())
+	}
+	// table types pick the archetypes, sparse types filter the rows, a sparse-only query walks the smallest set
+	pub fn entities_with(&self, types: &[TypeId]) -> impl Iterator<Item = EntityId> + '_ {
+		let table: HashSet<TypeId> = types.iter().filter(|tid| !self.is_sparse(tid)).copied().collect();
+		let sparse: Vec<&TypeId> = types.iter().filter(|tid| self.is_sparse(tid)).collect();
+		let sets: Vec<&SparseSet> = sparse.iter().filter_map(|tid| self.sparse.get(*tid)).collect();
+		let missing = sets.len() != sparse.len();
+		let rows = if table.is_empty() && !sets.is_empty() {
+			let smallest = sets.iter().min_by_key(|set| set.len()).unwrap();
+			itertools::Either::Left(smallest.entities().iter().copied())
+		} else {
+			itertools::Either::Right(self.archetypes.positions()
+				.filter(move |node| node.types().is_superset(&table))
+				.flat_map(|node| node.element().get_component_vec::<EntityId>().copied()))
+		};
+		rows.filter(move |e| !missing && sets.iter().all(|set| set.contains(*e)))
 	}
 	pub fn destroy_entity(&mut self, entity: EntityId) {
-		if let Some((a_id, row)) = self.entity_index.get(&entity).cloned() {
+		if self.contains_entity(entity) {
+			self.record_changes();
+			self.journal_push(JournalOp::Destroy { entity });
+			self.undo_despawn(entity);
+		}
+		self.despawn(entity);
+	}
+	// one archetype lookup for the whole batch, values are written straight into its columns instead of moved row by row
+	pub fn spawn_batch<B: Bundle>(&mut self, bundles: impl IntoIterator<Item = B>) -> Vec<EntityId> {
+		B::register(&mut self.registry);
+		let types = B::type_ids();
+		if self.is_recording_undo() || self.is_journaling() || types.iter().any(|tid| self.is_sparse(tid)) {
+			return bundles.into_iter().map(|bundle| self.spawn(bundle)).collect();
+		}
+		let bundles = bundles.into_iter();
+		let (hint, _) = bundles.size_hint();
+		let drops = types.iter().map(|tid| self.registry.get(tid).and_then(|info| info.drop_fn())).collect();
+		let a_id = self.get_or_create_archetype(&types);
+		let arche = self.archetypes.get_mut(&a_id).unwrap();
+		arche.grow(hint);
+		let first = arche.len();
+		// the iterator is user code, if it panics the rows written so far must not outlive it
+		let mut guard = BatchGuard { columns: arche.get_columns_mut(&types).unwrap(), drops, first };
+		let mut spawned = Vec::with_capacity(hint);
+		for bundle in bundles {
+			bundle.write(&mut guard.columns);
+			for column in guard.columns.iter_mut() {
+				column.mark_changed(first + spawned.len(), self.change_tick);
+			}
+			spawned.push(EntityId(self.entity_ids.next()));
+		}
+		guard.finish();
+		let ids = arche.get_column_mut(&TypeId::of::<EntityId>()).unwrap();
+		ids.reserve(spawned.len());
+		for id in spawned.iter() {
+			ids.push(*id);
+		}
+		unsafe { arche.publish_extend(spawned.len()) };
+		for (row, id) in spawned.iter().enumerate() {
+			self.entity_index.insert(*id, (a_id, first + row));
+		}
+		spawned
+	}
+	// room for `additional` more entities with exactly the bundle's components, allocated ahead of time
+	pub fn reserve<B: Bundle>(&mut self, additional: usize) {
+		let a_id = self.archetype_for::<B>();
+		self.archetypes.get_mut(&a_id).unwrap().reserve(additional);
+	}
+	// hands back every chunk and table slot no entity is using, e.g. after a level unloads
+	pub fn shrink_to_fit(&mut self) {
+		for node in self.archetypes.positions_mut() {
+			node.element_mut().shrink_to_fit();
+		}
+		for set in self.sparse.values_mut() {
+			set.shrink_to_fit();
+		}
+		self.entity_index.shrink_to_fit();
+	}
+	// removes archetypes without entities, they are rebuilt on demand if the combination comes back.
+	// reserved ones are kept, they are empty on purpose
+	pub fn compact_archetypes(&mut self) -> usize {
+		let removed = self.archetypes.retain(|_, arche| arche.len() > 0 || arche.is_reserved());
+		if !removed.is_empty() {
+			for archetypes in self.component_index.values_mut() {
+				for a_id in removed.iter() {
+					archetypes.remove(a_id);
+				}
+			}
+			self.component_index.retain(|_, archetypes| !archetypes.is_empty());
+		}
+		removed.len()
+	}
+	// once the graph holds `limit` nodes, empty ones are collected before another is created
+	pub fn set_archetype_limit(&mut self, limit: Option<usize>) {
+		self.archetype_limit = limit;
+	}
+	pub fn archetype_limit(&self) -> Option<usize> {
+		self.archetype_limit
+	}
+	// only called before an edge is walked, so no archetype that is about to be used can be empty and in flight
+	fn enforce_archetype_limit(&mut self) {
+		if self.archetype_limit.is_some_and(|limit| self.archetypes.len() >= limit) {
+			self.compact_archetypes();
+		}
+	}
+	fn despawn(&mut self, entity: EntityId) {
+		let registry = &self.registry;
+		for (tid, set) in self.sparse.iter_mut() {
+			set.remove(entity, registry.get(tid).and_then(|info| info.drop_fn()));
+		}
+		if let Some((a_id, row)) = self.entity_index.remove(&entity) {
 			let arche = self.archetypes.get_mut(&a_id).unwrap();
-			arche.remove_entity(row);
+			arche.remove_entity(row, |tid| registry.get(tid).and_then(|info| info.drop_fn()));
 			// update references if swapped with end
 			self.check_swapped_row(row, a_id);
 		}
 	}
 	pub fn attach_component<CompType: 'static>(&mut self, entity: EntityId, component: CompType) {
-		if let Some((old_id, old_row)) = self.entity_index.get(&entity).copied() {
+		self.registry.register::<CompType>();
+		self.record_changes();
+		if self.is_recording_undo() || self.is_sparse(&TypeId::of::<CompType>()) {
+			self.put_column(entity, ComponentVec::from([component]));
+		} else if let Some(existing) = self.get_component_mut::<CompType>(entity) {
+			*existing = component;
+		} else if let Some((old_id, old_row)) = self.entity_index.get(&entity).copied() {
 			// find new archetype
 			let new_id = if let Some(new_arche) = self.archetypes.get_superset_with::<CompType>(old_id) {
 				// archetype exists
 				new_arche
 			} else {
 				// create new archetype
+				self.enforce_archetype_limit();
 				let new_arche = self.archetypes.create_superset_with::<CompType>(old_id);
-				self.update_component_index(TypeId::of::<CompType>(), new_arche);
+				self.update_component_index(new_arche);
 				new_arche
 			};
 			// move to new archetype
 			{
 				let [old_arche, new_arche] = self.archetypes.get_many([&old_id, &new_id]).unwrap();
 				let new_row = old_arche.upgrade_entity(new_arche, old_row, component);
+				new_arche.get_column_mut(&TypeId::of::<CompType>()).unwrap().mark_changed(new_row, self.change_tick);
 				self.entity_index.insert(entity, (new_id, new_row));
 			}
 			// update other affected rows
 			self.check_swapped_row(old_row, old_id);
 		}
+		self.journal_attach(entity, &TypeId::of::<CompType>());
+	}
+	fn attach_column(&mut self, entity: EntityId, column: &mut ComponentVec) {
+		if let Some((old_id, old_row)) = self.entity_index.get(&entity).copied() {
+			let tid = column.type_id();
+			if self.is_sparse(&tid) {
+				let drop = self.registry.get(&tid).and_then(|info| info.drop_fn());
+				self.sparse.entry(tid).or_insert_with(|| SparseSet::new(column.imitate())).insert(entity, column, drop, self.change_tick);
+				return;
+			}
+			if let Some(existing) = self.archetypes.get_mut(&old_id).unwrap().get_column_mut(&tid) {
+				if let Some(drop) = self.registry.get(&tid).and_then(|info| info.drop_fn()) {
+					unsafe { drop(existing.get_ptr(old_row)) };
+				}
+				existing.replace_with_tail(old_row, column);
+				existing.mark_changed(old_row, self.change_tick);
+				return;
+			}
+			let new_id = if let Some(new_arche) = self.archetypes.get_superset(old_id, &tid) {
+				new_arche
+			} else {
+				self.enforce_archetype_limit();
+				let new_arche = self.archetypes.create_superset(old_id, column);
+				self.update_component_index(new_arche);
+				new_arche
+			};
+			{
+				let [old_arche, new_arche] = self.archetypes.get_many([&old_id, &new_id]).unwrap();
+				let new_row = old_arche.upgrade_entity_with(new_arche, old_row, column);
+				new_arche.get_column_mut(&tid).unwrap().mark_changed(new_row, self.change_tick);
+				self.entity_index.insert(entity, (new_id, new_row));
+			}
+			self.check_swapped_row(old_row, old_id);
+		}
 	}
 	pub fn detach_component<CompType: 'static>(&mut self, entity: EntityId) {
+		self.detach_type(entity, &TypeId::of::<CompType>())
+	}
+	pub fn detach_type(&mut self, entity: EntityId, tid: &TypeId) {
+		self.record_changes();
+		self.journal_detach(entity, tid);
+		if self.is_recording_undo() {
+			self.undo_swap(entity, tid, None);
+		} else {
+			self.remove_type(entity, tid);
+		}
+	}
+	fn remove_type(&mut self, entity: EntityId, tid: &TypeId) {
+		let drop = self.registry.get(tid).and_then(|info| info.drop_fn());
+		self.remove_type_with(entity, tid, drop);
+	}
+	// None forgets the value instead of dropping it, for when it was copied out first
+	fn remove_type_with(&mut self, entity: EntityId, tid: &TypeId, drop: Option<unsafe fn(*mut u8)>) {
+		if self.is_sparse(tid) {
+			if let Some(set) = self.sparse.get_mut(tid) {
+				set.remove(entity, drop);
+			}
+			return;
+		}
 		if let Some((old_id, old_row)) = self.entity_index.get(&entity).copied() {
-			let new_id = if let Some(new_id) = self.archetypes.get_subset_without::<CompType>(old_id) {
+			if self.archetypes.get(&old_id).unwrap().get_column(tid).is_none() {
+				return;
+			}
+			let new_id = if let Some(new_id) = self.archetypes.get_subset(old_id, tid) {
 				new_id
 			} else {
-				let new_id = self.archetypes.create_subset_without::<CompType>(old_id);
-				self.update_component_index(TypeId::of::<CompType>(), new_id);
+				self.enforce_archetype_limit();
+				let new_id = self.archetypes.create_subset(old_id, tid);
+				self.update_component_index(new_id);
 				new_id
 			};
 			{
 				let [old_arche, new_arche] = self.archetypes.get_many([&old_id, &new_id]).unwrap();
-				let new_row = old_arche.downgrade_entity(new_arche, old_row);
+				let new_row = old_arche.downgrade_entity(new_arche, old_row, drop);
 				self.entity_index.insert(entity, (new_id, new_row));
 			}
 			self.check_swapped_row(old_row, old_id);
 		}
 	}
+	pub fn is_sparse(&self, tid: &TypeId) -> bool {
+		self.registry.get(tid).is_some_and(|info| info.is_sparse())
+	}
 	pub fn has_component<T:'static>(&self, entity: EntityId) -> bool {
 		let tid = TypeId::of::<T>();
+		if self.is_sparse(&tid) {
+			return self.sparse.get(&tid).is_some_and(|set| set.contains(entity));
+		}
 		if let Some(types) = self.component_index.get(&tid) {
 			if let Some((aid, _)) = self.entity_index.get(&entity) {
 				return types.contains(aid);
@@ -100,18 +395,151 @@ impl ECS {
 		false
 	}
 	pub fn get_component<T:'static>(&self, entity: EntityId) -> Option<&T> {
-		if let Some((a_id, row)) = self.entity_index.get(&entity).copied() {
-			let a = self.archetypes.get(&a_id).unwrap();
-			return a.get_component::<T>(row);
-		}
-		None
+		let (cv, row) = self.column_of(entity, &TypeId::of::<T>())?;
+		cv.get::<T>(row)
 	}
 	pub fn get_component_mut<T:'static>(&mut self, entity: EntityId) -> Option<&mut T> {
-		if let Some((a_id, row)) = self.entity_index.get(&entity).copied() {
-			let a = self.archetypes.get_mut(&a_id).unwrap();
-			return a.get_component_mut::<T>(row);
+		let tick = self.change_tick;
+		let (column, row) = self.column_of_mut(entity, &TypeId::of::<T>())?;
+		column.mark_changed(row, tick);
+		column.get_mut::<T>(row)
+	}
+	// wherever the component lives, its column and the entity's row in it
+	pub(crate) fn column_of(&self, entity: EntityId, tid: &TypeId) -> Option<(&ComponentVec, usize)> {
+		if self.is_sparse(tid) {
+			let set = self.sparse.get(tid)?;
+			return Some((set.column(), set.row(entity)?));
+		}
+		let (a_id, row) = self.entity_index.get(&entity).copied()?;
+		Some((self.archetypes.get(&a_id).unwrap().get_column(tid)?, row))
+	}
+	fn column_of_mut(&mut self, entity: EntityId, tid: &TypeId) -> Option<(&mut ComponentVec, usize)> {
+		if self.is_sparse(tid) {
+			let set = self.sparse.get_mut(tid)?;
+			let row = set.row(entity)?;
+			return Some((set.column_mut(), row));
+		}
+		let (a_id, row) = self.entity_index.get(&entity).copied()?;
+		Some((self.archetypes.get_mut(&a_id).unwrap().get_column_mut(tid)?, row))
+	}
+	// moves the value out, the world no longer owns it afterwards
+	pub(crate) fn take_column(&mut self, entity: EntityId, tid: &TypeId) -> Option<ComponentVec> {
+		if self.is_sparse(tid) {
+			return self.sparse.get_mut(tid)?.take(entity);
+		}
+		let (cv, row) = self.column_of(entity, tid)?;
+		let column = unsafe { cv.copy_row(row) };
+		self.remove_type_with(entity, tid, None);
+		Some(column)
+	}
+	pub fn component_types(&self, entity: EntityId) -> Vec<TypeId> {
+		if let Some((a_id, _)) = self.entity_index.get(&entity) {
+			let mut types: Vec<TypeId> = self.archetypes.get(a_id).unwrap().types().copied().collect();
+			types.extend(self.sparse.iter().filter(|(_, set)| set.contains(entity)).map(|(tid, _)| *tid));
+			types.sort();
+			return types;
+		}
+		Vec::new()
+	}
+	pub fn debug_component(&self, entity: EntityId, tid: &TypeId) -> Option<String> {
+		let (cv, row) = self.column_of(entity, tid)?;
+		self.registry.get(tid)?.debug(cv, row)
+	}
+	pub fn attach_default(&mut self, entity: EntityId, tid: &TypeId) -> bool {
+		let mut column = match self.registry.get(tid) {
+			Some(info) if info.has_default() => info.new_column(),
+			_ => return false,
+		};
+		self.registry.get(tid).unwrap().default_into(&mut column);
+		self.record_changes();
+		self.put_column(entity, column);
+		self.journal_attach(entity, tid);
+		true
+	}
+	pub fn clone_component(&mut self, src: EntityId, dst: EntityId, tid: &TypeId) -> bool {
+		let column = match (self.registry.get(tid), self.column_of(src, tid)) {
+			(Some(info), Some((cv, row))) if info.has_clone() => {
+				let mut column = info.new_column();
+				info.clone_into(cv, row, &mut column);
+				column
+			},
+			_ => return false,
+		};
+		self.record_changes();
+		self.put_column(dst, column);
+		self.journal_attach(dst, tid);
+		true
+	}
+	fn put_column(&mut self, entity: EntityId, mut column: ComponentVec) {
+		if self.is_recording_undo() {
+			let tid = column.type_id();
+			self.undo_swap(entity, &tid, Some(column));
+		} else {
+			self.attach_column(entity, &mut column);
+		}
+	}
+	pub fn find_archetype(&self, signature: &[TypeId]) -> Option<ArchetypeId> {
+		self.archetypes.find_archetype(signature)
+	}
+	// the archetype an entity spawned with the bundle ends up in, sparse components left out
+	pub fn archetype_for<B: Bundle>(&mut self) -> ArchetypeId {
+		B::register(&mut self.registry);
+		let signature: Vec<TypeId> = B::type_ids().into_iter().filter(|tid| !self.is_sparse(tid)).collect();
+		self.get_or_create_archetype(&signature)
+	}
+	// every type has to be registered, the entity id column is added if it's missing
+	pub(crate) fn get_or_create_archetype(&mut self, signature: &[TypeId]) -> ArchetypeId {
+		if let Some(a_id) = self.archetypes.find_archetype(signature) {
+			return a_id;
+		}
+		self.enforce_archetype_limit();
+		let columns: Vec<ComponentVec> = signature.iter()
+			.map(|tid| self.registry.get(tid).expect("Unregistered component").new_column())
+			.collect();
+		let a_id = self.archetypes.create_archetype(&columns);
+		self.update_component_index(a_id);
+		a_id
+	}
+	// gives an entity several new components with a single move, sparse ones go to their sets
+	fn insert_columns(&mut self, entity: EntityId, columns: &mut [ComponentVec]) {
+		let Some((old_id, old_row)) = self.entity_index.get(&entity).copied() else {
+			return;
+		};
+		let mut signature: Vec<TypeId> = self.archetypes.get(&old_id).unwrap().types().copied().collect();
+		let (mut table, rest): (Vec<&mut ComponentVec>, Vec<&mut ComponentVec>) = columns.iter_mut()
+			.partition(|c| !self.is_sparse(&c.type_id()) && !signature.contains(&c.type_id()));
+		signature.extend(table.iter().map(|c| c.type_id()));
+		if !table.is_empty() {
+			let new_id = self.get_or_create_archetype(&signature);
+			let [old_arche, new_arche] = self.archetypes.get_many([&old_id, &new_id]).unwrap();
+			let new_row = old_arche.upgrade_entity_many(new_arche, old_row, &mut table);
+			for column in table.iter() {
+				new_arche.get_column_mut(&column.type_id()).unwrap().mark_changed(new_row, self.change_tick);
+			}
+			self.entity_index.insert(entity, (new_id, new_row));
+			self.check_swapped_row(old_row, old_id);
+		}
+		for column in rest {
+			self.attach_column(entity, column);
+		}
+	}
+	// creates the entity directly in the bundle's archetype instead of moving it once per component
+	pub fn spawn<B: Bundle>(&mut self, bundle: B) -> EntityId {
+		B::register(&mut self.registry);
+		let types = B::type_ids();
+		let entity = self.create_entity();
+		let mut columns = bundle.into_columns();
+		if self.is_recording_undo() {
+			for column in columns {
+				self.put_column(entity, column);
+			}
+		} else {
+			self.insert_columns(entity, &mut columns);
 		}
-		None
+		for tid in types {
+			self.journal_attach(entity, &tid);
+		}
+		entity
 	}
 	fn check_swapped_row(&mut self, new_spot: usize, a_id: ArchetypeId) {
 		let arche = self.archetypes.get(&a_id).unwrap();
@@ -120,11 +548,11 @@ impl ECS {
 			self.entity_index.insert(moved, (a_id, new_spot));
 		}
 	}
-	fn update_component_index(&mut self, tid: TypeId, new_arche: ArchetypeId) {
-		if let Some(set) = self.component_index.get_mut(&tid) {
-			set.insert(new_arche);
-		} else {
-			self.component_index.insert(tid, HashSet::from([new_arche]));
+	fn update_component_index(&mut self, new_arche: ArchetypeId) {
+		// a new archetype belongs under every one of its types, not just the one that was added
+		for tid in self.archetypes.get(&new_arche).unwrap().types() {
+			self.component_index.entry(*tid).or_default().insert(new_arche);
+			self.registry.mark_tabled(tid);
 		}
 	}
 }
@@ -142,6 +570,25 @@ macro_rules! create_entity_from {
 	};
 }
 
+// works across table and sparse storage, at the cost of a lookup per component and row
+#[macro_export]
+macro_rules! query {
+	($ecs:expr, $($t:tt)+) => {{
+		let ecs = &$ecs;
+		$crate::query!(@fetch ecs, e, [] [] $($t)+)
+	}};
+	// `Shared<&T>` reads through the handle into the pool, anything else is a plain component
+	(@fetch $ecs:ident, $e:ident, [$($ids:expr,)*] [$($get:expr,)*] Shared<&$t:ty> $(, $($rest:tt)*)?) => {
+		$crate::query!(@fetch $ecs, $e, [$($ids,)* std::any::TypeId::of::<$crate::Shared<$t>>(),] [$($get,)* $ecs.get_shared::<$t>($e)?,] $($($rest)*)?)
+	};
+	(@fetch $ecs:ident, $e:ident, [$($ids:expr,)*] [$($get:expr,)*] $t:path $(, $($rest:tt)*)?) => {
+		$crate::query!(@fetch $ecs, $e, [$($ids,)* std::any::TypeId::of::<$t>(),] [$($get,)* $ecs.get_component::<$t>($e)?,] $($($rest)*)?)
+	};
+	(@fetch $ecs:ident, $e:ident, [$($ids:expr,)*] [$($get:expr,)*]) => {
+		$ecs.entities_with(&[$($ids),*]).filter_map(move |$e| Some(($e, $($get),*)))
+	};
+}
+
 #[macro_export]
 macro_rules! iter_components {
 	($ecs:expr, $($t:path),+) => {
@@ -153,7 +600,7 @@ macro_rules! iter_components {
 				} else {
 					None
 				}
-			}).flat_map(|( $([< $t:snake:lower >]),+ )| itertools::izip!($( [< $t:snake:lower >].iter() ),+))
+			}).flat_map(|( $([< $t:snake:lower >]),+ )| itertools::izip!($( [< $t:snake:lower >] ),+))
 		}
 	}
 }
@@ -161,25 +608,62 @@ macro_rules! iter_components {
 #[macro_export]
 macro_rules! iter_components_mut {
 	($ecs:expr, $($t:path),*) => {
-		paste::paste! {
-			$ecs.archetypes_mut().positions_mut().filter_map(|node| {
+		paste::paste! {{
+			let tick = $ecs.change_tick();
+			$ecs.archetypes_mut().positions_mut().filter_map(move |node| {
 				if node.types().is_superset(&hashbrown::HashSet::from([$(std::any::TypeId::of::<$t>()),+])) {
 					let [$([< $t:snake:lower >]),+] = node.element_mut().get_many_comp_vec_mut([$(&std::any::TypeId::of::<$t>()),+]);
-					Some(( $( [<$t:snake:lower>].as_mut_slice::<$t>() ),+ ))
+					$( [<$t:snake:lower>].mark_all_changed(tick); )+
+					Some(( $( [<$t:snake:lower>].iter_mut::<$t>() ),+ ))
 				} else {
 					None
 				}
-			}).flat_map(|( $([<$t:snake:lower>]),+)| itertools::izip!($([<$t:snake:lower>].iter_mut()),+))
+			}).flat_map(|( $([<$t:snake:lower>]),+)| itertools::izip!($([<$t:snake:lower>]),+))
+		}}
+	}
+}
+
+// one tuple of equally long slices per archetype chunk
+#[macro_export]
+macro_rules! iter_chunks {
+	($ecs:expr, $($t:path),+) => {
+		paste::paste! {
+			$ecs.archetypes().positions().filter_map(|node| {
+				if node.types().is_superset(&hashbrown::HashSet::from([$(std::any::TypeId::of::<$t>()),+])) {
+					let arche = node.element();
+					Some(( $(arche.get_column(&std::any::TypeId::of::<$t>()).unwrap().chunks::<$t>()),+ ))
+				} else {
+					None
+				}
+			}).flat_map(|( $([< $t:snake:lower >]),+ )| itertools::izip!($( [< $t:snake:lower >] ),+))
 		}
 	}
 }
 
+#[macro_export]
+macro_rules! iter_chunks_mut {
+	($ecs:expr, $($t:path),+) => {
+		paste::paste! {{
+			let tick = $ecs.change_tick();
+			$ecs.archetypes_mut().positions_mut().filter_map(move |node| {
+				if node.types().is_superset(&hashbrown::HashSet::from([$(std::any::TypeId::of::<$t>()),+])) {
+					let [$([< $t:snake:lower >]),+] = node.element_mut().get_many_comp_vec_mut([$(&std::any::TypeId::of::<$t>()),+]);
+					$( [<$t:snake:lower>].mark_all_changed(tick); )+
+					Some(( $( [<$t:snake:lower>].chunks_mut::<$t>() ),+ ))
+				} else {
+					None
+				}
+			}).flat_map(|( $([<$t:snake:lower>]),+)| itertools::izip!($([<$t:snake:lower>]),+))
+		}}
+	}
+}
+
 #[macro_export]
 macro_rules! iter_components_cast {
 	($ecs:expr, [$first:path, $($t:path),*] as $cast:path) => {
 		$ecs.archetypes().positions().filter_map(|node| {
 			if node.types().contains(&std::any::TypeId::of::<$first>()) {
-				Some(node.element().get_component_vec::<$first>().iter().map(|c| c as &dyn $cast).collect::<Vec<&dyn $cast>>())
+				Some(node.element().get_component_vec::<$first>().map(|c| c as &dyn $cast).collect::<Vec<&dyn $cast>>())
 			} else {
 				None
 			}
@@ -187,7 +671,7 @@ macro_rules! iter_components_cast {
 		$(
 			.chain($ecs.archetypes().positions().filter_map(|node| {
 				if node.types().contains(&std::any::TypeId::of::<$t>()) {
-					Some(node.element().get_component_vec::<$t>().iter().map(|c| c as &dyn $cast).collect::<Vec<&dyn $cast>>())
+					Some(node.element().get_component_vec::<$t>().map(|c| c as &dyn $cast).collect::<Vec<&dyn $cast>>())
 				} else {
 					None
 				}
@@ -0,0 +1,134 @@
+use serde::{Serialize, Deserialize};
+
+use crate::{binary::{put_u32, put_u64}, BinaryCodec, BinaryError, BinaryReader, EntityId, StableHash, StableHasher, ECS};
+
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct InstanceKey(u32);
+
+// several values of one type on the same entity, keys stay valid while other instances come and go.
+// the persistence traits forward to T, downstream crates can't implement them for Multi<T> themselves
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Multi<T> {
+	items: Vec<(InstanceKey, T)>,
+	next: u32,
+}
+impl<T> Default for Multi<T> {
+	fn default() -> Self {
+		Self { items: Vec::new(), next: 0 }
+	}
+}
+impl<T> Multi<T> {
+	pub fn new() -> Self {
+		Self::default()
+	}
+	pub fn len(&self) -> usize {
+		self.items.len()
+	}
+	pub fn is_empty(&self) -> bool {
+		self.items.is_empty()
+	}
+	pub fn insert(&mut self, value: T) -> InstanceKey {
+		let key = InstanceKey(self.next);
+		self.next += 1;
+		self.items.push((key, value));
+		key
+	}
+	pub fn remove(&mut self, key: InstanceKey) -> Option<T> {
+		let index = self.items.iter().position(|(k, _)| *k == key)?;
+		Some(self.items.remove(index).1)
+	}
+	pub fn get(&self, key: InstanceKey) -> Option<&T> {
+		self.items.iter().find(|(k, _)| *k == key).map(|(_, v)| v)
+	}
+	pub fn get_mut(&mut self, key: InstanceKey) -> Option<&mut T> {
+		self.items.iter_mut().find(|(k, _)| *k == key).map(|(_, v)| v)
+	}
+	// instances in insertion order
+	pub fn iter(&self) -> impl Iterator<Item = (InstanceKey, &T)> {
+		self.items.iter().map(|(k, v)| (*k, v))
+	}
+	pub fn iter_mut(&mut self) -> impl Iterator<Item = (InstanceKey, &mut T)> {
+		self.items.iter_mut().map(|(k, v)| (*k, v))
+	}
+	pub fn values(&self) -> impl Iterator<Item = &T> {
+		self.items.iter().map(|(_, v)| v)
+	}
+}
+
+impl<T: StableHash> StableHash for Multi<T> {
+	fn stable_hash(&self, state: &mut StableHasher) {
+		self.next.stable_hash(state);
+		state.write_u64(self.items.len() as u64);
+		for (key, value) in self.items.iter() {
+			key.0.stable_hash(state);
+			value.stable_hash(state);
+		}
+	}
+}
+impl<T: BinaryCodec> BinaryCodec for Multi<T> {
+	fn encode(&self, out: &mut Vec<u8>) {
+		put_u32(out, self.next);
+		put_u64(out, self.items.len() as u64);
+		for (key, value) in self.items.iter() {
+			put_u32(out, key.0);
+			value.encode(out);
+		}
+	}
+	fn decode(input: &mut BinaryReader) -> Result<Self, BinaryError> {
+		let next = input.u32()?;
+		let len = input.u64()? as usize;
+		let mut items = Vec::new();
+		for _ in 0..len {
+			let key = InstanceKey(input.u32()?);
+			items.push((key, T::decode(input)?));
+		}
+		Ok(Self { items, next })
+	}
+}
+
+impl ECS {
+	pub fn add_instance<T:'static + Clone>(&mut self, entity: EntityId, value: T) -> Option<InstanceKey> {
+		if !self.contains_entity(entity) {
+			return None;
+		}
+		// the undo history holds on to the old container, so the edit goes through a copy
+		if self.is_recording_undo() {
+			let mut multi = self.get_component::<Multi<T>>(entity).cloned().unwrap_or_default();
+			let key = multi.insert(value);
+			self.attach_component(entity, multi);
+			return Some(key);
+		}
+		if let Some(multi) = self.get_component_mut::<Multi<T>>(entity) {
+			return Some(multi.insert(value));
+		}
+		let mut multi = Multi::new();
+		let key = multi.insert(value);
+		self.attach_component(entity, multi);
+		Some(key)
+	}
+	// the container goes away with its last instance so queries stop matching the entity
+	pub fn remove_instance<T:'static + Clone>(&mut self, entity: EntityId, key: InstanceKey) -> Option<T> {
+		if self.is_recording_undo() {
+			let mut multi = self.get_component::<Multi<T>>(entity)?.clone();
+			let value = multi.remove(key)?;
+			if multi.is_empty() {
+				self.detach_component::<Multi<T>>(entity);
+			} else {
+				self.attach_component(entity, multi);
+			}
+			return Some(value);
+		}
+		let multi = self.get_component_mut::<Multi<T>>(entity)?;
+		let value = multi.remove(key)?;
+		if multi.is_empty() {
+			self.detach_component::<Multi<T>>(entity);
+		}
+		Some(value)
+	}
+	pub fn instances<T:'static>(&self, entity: EntityId) -> impl Iterator<Item = &T> {
+		self.get_component::<Multi<T>>(entity).into_iter().flat_map(|multi| multi.values())
+	}
+	pub fn instance_count<T:'static>(&self, entity: EntityId) -> usize {
+		self.get_component::<Multi<T>>(entity).map_or(0, |multi| multi.len())
+	}
+}
@@ -0,0 +1,420 @@
+use std::{any::{TypeId, type_name}, alloc::Layout, fmt::{self, Debug}, mem, ptr};
+
+use hashbrown::HashMap;
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
+use serde_json::Value;
+
+use crate::{binary::{self, BinaryCodec, BinaryError, BinaryReader, Pod}, checksum::{StableHash, StableHasher}, component_vec::ComponentVec, EntityMap, MapEntities};
+
+#[derive(Hash, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct StableId(u64);
+impl StableId {
+	pub const fn new(id: u64) -> Self {
+		Self(id)
+	}
+	// FNV-1a, so the same name hashes identically on every build and platform
+	pub const fn from_name(name: &str) -> Self {
+		let bytes = name.as_bytes();
+		let mut hash = 0xcbf29ce484222325u64;
+		let mut i = 0;
+		while i < bytes.len() {
+			hash ^= bytes[i] as u64;
+			hash = hash.wrapping_mul(0x100000001b3);
+			i += 1;
+		}
+		Self(hash)
+	}
+	pub fn get(&self) -> u64 {
+		self.0
+	}
+}
+impl From<u64> for StableId {
+	fn from(id: u64) -> Self {
+		Self::new(id)
+	}
+}
+impl From<&str> for StableId {
+	fn from(name: &str) -> Self {
+		Self::from_name(name)
+	}
+}
+
+#[macro_export]
+macro_rules! stable_name {
+	($t:ident) => { concat!(module_path!(), "::", stringify!($t)) };
+}
+
+pub type JsonMigration = fn(Value) -> Result<Value, String>;
+pub type BinaryMigration = fn(&[u8], usize) -> Result<Vec<u8>, String>;
+
+#[derive(Debug)]
+pub enum MigrationError {
+	Missing { component: &'static str, from: u32, to: u32 },
+	Failed { component: &'static str, from: u32, message: String },
+	Newer { component: &'static str, found: u32, current: u32 },
+}
+impl fmt::Display for MigrationError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			MigrationError::Missing { component, from, to } =>
+				write!(f, "no migration for `{}` from version {} to {}", component, from, to),
+			MigrationError::Failed { component, from, message } =>
+				write!(f, "migrating `{}` from version {} failed: {}", component, from, message),
+			MigrationError::Newer { component, found, current } =>
+				write!(f, "`{}` was saved at version {}, newer than current version {}", component, found, current),
+		}
+	}
+}
+impl std::error::Error for MigrationError {}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Rollback {
+	Copy,
+	Clone,
+}
+
+type DecodeFn = fn(&mut BinaryReader, usize, &mut ComponentVec) -> Result<(), BinaryError>;
+type EqFn = fn(&ComponentVec, usize, &ComponentVec, usize) -> bool;
+
+pub struct ComponentInfo {
+	type_id: TypeId,
+	name: &'static str,
+	stable_id: StableId,
+	layout: Layout,
+	drop: Option<unsafe fn(*mut u8)>,
+	column: fn() -> ComponentVec,
+	debug: Option<fn(&ComponentVec, usize) -> String>,
+	eq: Option<EqFn>,
+	clone: Option<fn(&ComponentVec, usize, &mut ComponentVec)>,
+	default: Option<fn(&mut ComponentVec)>,
+	serialize: Option<fn(&ComponentVec, usize) -> serde_json::Result<Value>>,
+	deserialize: Option<fn(Value, &mut ComponentVec) -> serde_json::Result<()>>,
+	map_entities: Option<fn(&mut ComponentVec, usize, &EntityMap)>,
+	pod: bool,
+	encode: Option<fn(&ComponentVec, &mut Vec<u8>)>,
+	decode: Option<DecodeFn>,
+	rollback: Option<Rollback>,
+	hash: Option<fn(&ComponentVec, usize, &mut StableHasher)>,
+	replicated: bool,
+	sparse: bool,
+	// set once an archetype has a column for the type, it can't move to sparse storage after that
+	tabled: bool,
+	version: u32,
+	json_migrations: HashMap<u32, JsonMigration>,
+	binary_migrations: HashMap<u32, BinaryMigration>,
+}
+pub(crate) fn drop_fn_of<T>() -> Option<unsafe fn(*mut u8)> {
+	unsafe fn drop_as<T>(ptr: *mut u8) {
+		ptr::drop_in_place(ptr.cast::<T>())
+	}
+	if mem::needs_drop::<T>() { Some(drop_as::<T>) } else { None }
+}
+
+impl ComponentInfo {
+	fn of<T:'static>() -> Self {
+		Self {
+			type_id: TypeId::of::<T>(),
+			name: type_name::<T>(),
+			stable_id: StableId::from_name(type_name::<T>()),
+			layout: Layout::new::<T>(),
+			drop: drop_fn_of::<T>(),
+			column: ComponentVec::new_as::<T>,
+			debug: None,
+			eq: None,
+			clone: None,
+			default: None,
+			serialize: None,
+			deserialize: None,
+			map_entities: None,
+			pod: false,
+			encode: None,
+			decode: None,
+			rollback: None,
+			hash: None,
+			replicated: false,
+			sparse: false,
+			tabled: false,
+			version: 0,
+			json_migrations: HashMap::new(),
+			binary_migrations: HashMap::new(),
+		}
+	}
+	fn assert_type<T:'static>(&self) {
+		assert!(self.type_id == TypeId::of::<T>(), "{} registered as {}", type_name::<T>(), self.name);
+	}
+	// scenes, snapshots, replication and the journal only walk archetype tables
+	fn assert_persistable(&self) {
+		assert!(!self.sparse, "Component {} uses sparse storage, it can't be saved or replicated", self.name);
+	}
+	pub fn type_id(&self) -> TypeId {
+		self.type_id
+	}
+	pub fn name(&self) -> &'static str {
+		self.name
+	}
+	pub fn stable_id(&self) -> StableId {
+		self.stable_id
+	}
+	pub fn version(&self) -> u32 {
+		self.version
+	}
+	pub fn size(&self) -> usize {
+		self.layout.size()
+	}
+	pub fn align(&self) -> usize {
+		self.layout.align()
+	}
+	pub fn layout(&self) -> Layout {
+		self.layout
+	}
+	pub fn drop_fn(&self) -> Option<unsafe fn(*mut u8)> {
+		self.drop
+	}
+	pub fn new_column(&self) -> ComponentVec {
+		(self.column)()
+	}
+	pub fn has_debug(&self) -> bool { self.debug.is_some() }
+	pub fn has_eq(&self) -> bool { self.eq.is_some() }
+	pub fn has_clone(&self) -> bool { self.clone.is_some() }
+	pub fn has_default(&self) -> bool { self.default.is_some() }
+	pub fn has_serde(&self) -> bool { self.serialize.is_some() && self.deserialize.is_some() }
+	pub fn is_pod(&self) -> bool { self.pod }
+	pub fn is_binary(&self) -> bool { self.pod || (self.encode.is_some() && self.decode.is_some()) }
+	pub fn is_rollback(&self) -> bool { self.rollback.is_some() }
+	pub fn has_stable_hash(&self) -> bool { self.hash.is_some() }
+	pub fn is_replicated(&self) -> bool { self.replicated }
+	pub fn is_sparse(&self) -> bool { self.sparse }
+	pub fn with_debug<T:'static + Debug>(&mut self) -> &mut Self {
+		self.assert_type::<T>();
+		self.debug = Some(|cv, row| format!("{:?}", cv.get::<T>(row).unwrap()));
+		self
+	}
+	pub fn with_eq<T:'static + PartialEq>(&mut self) -> &mut Self {
+		self.assert_type::<T>();
+		self.eq = Some(|a, a_row, b, b_row| a.get::<T>(a_row).unwrap() == b.get::<T>(b_row).unwrap());
+		self
+	}
+	pub fn with_clone<T:'static + Clone>(&mut self) -> &mut Self {
+		self.assert_type::<T>();
+		self.clone = Some(|src, row, dst| dst.push(src.get::<T>(row).unwrap().clone()));
+		self
+	}
+	pub fn with_default<T:'static + Default>(&mut self) -> &mut Self {
+		self.assert_type::<T>();
+		self.default = Some(|dst| dst.push(T::default()));
+		self
+	}
+	pub fn with_serde<T:'static + Serialize + DeserializeOwned>(&mut self) -> &mut Self {
+		self.assert_type::<T>();
+		self.assert_persistable();
+		self.serialize = Some(|cv, row| serde_json::to_value(cv.get::<T>(row).unwrap()));
+		self.deserialize = Some(|value, dst| {
+			dst.push(serde_json::from_value::<T>(value)?);
+			Ok(())
+		});
+		self
+	}
+	pub fn with_map_entities<T:'static + MapEntities>(&mut self) -> &mut Self {
+		self.assert_type::<T>();
+		self.map_entities = Some(|cv, row, map| cv.get_mut::<T>(row).unwrap().map_entities(map));
+		self
+	}
+	pub fn with_pod<T: Pod>(&mut self) -> &mut Self {
+		self.assert_type::<T>();
+		self.assert_persistable();
+		self.pod = true;
+		self
+	}
+	pub fn with_codec<T: BinaryCodec>(&mut self) -> &mut Self {
+		self.assert_type::<T>();
+		self.assert_persistable();
+		self.encode = Some(binary::encode_column::<T>);
+		self.decode = Some(binary::decode_column::<T>);
+		self
+	}
+	pub fn with_rollback<T:'static + Clone>(&mut self) -> &mut Self {
+		self.with_clone::<T>();
+		self.rollback = Some(Rollback::Clone);
+		self
+	}
+	pub fn with_rollback_copy<T:'static + Copy>(&mut self) -> &mut Self {
+		self.assert_type::<T>();
+		self.rollback = Some(Rollback::Copy);
+		self
+	}
+	pub(crate) fn rollback_column(&self, src: &ComponentVec) -> ComponentVec {
+		match self.rollback.expect("Not a rollback component") {
+			Rollback::Copy => unsafe { src.duplicate() },
+			Rollback::Clone => {
+				let mut dst = src.imitate();
+				dst.reserve(src.len());
+				for row in 0..src.len() {
+					self.clone_into(src, row, &mut dst);
+				}
+				dst
+			},
+		}
+	}
+	pub(crate) fn rollback_row(&self, src: &ComponentVec, row: usize, dst: &mut ComponentVec) {
+		match self.rollback.expect("Not a rollback component") {
+			Rollback::Copy => unsafe {
+				dst.extend_from_bytes(src.row_bytes(row), 1);
+			},
+			Rollback::Clone => { self.clone_into(src, row, dst); },
+		}
+	}
+	pub fn with_stable_hash<T:'static + StableHash>(&mut self) -> &mut Self {
+		self.assert_type::<T>();
+		self.hash = Some(|cv, row, state| cv.get::<T>(row).unwrap().stable_hash(state));
+		self
+	}
+	pub fn with_replication<T:'static + Serialize + DeserializeOwned>(&mut self) -> &mut Self {
+		self.replicated = true;
+		self.with_serde::<T>()
+	}
+	// has to be chosen before the first component of this type is attached, and rules out saving and replication
+	pub fn with_sparse_storage(&mut self) -> &mut Self {
+		assert!(!self.tabled, "Component {} is already stored in an archetype", self.name);
+		assert!(!self.has_serde() && !self.is_binary() && !self.replicated, "Component {} is saved or replicated, it can't use sparse storage", self.name);
+		self.sparse = true;
+		self
+	}
+	pub fn with_version(&mut self, version: u32) -> &mut Self {
+		self.version = version;
+		self
+	}
+	// each migration upgrades data saved at `from` to `from + 1`
+	pub fn with_json_migration(&mut self, from: u32, migration: JsonMigration) -> &mut Self {
+		self.json_migrations.insert(from, migration);
+		self
+	}
+	pub fn with_binary_migration(&mut self, from: u32, migration: BinaryMigration) -> &mut Self {
+		self.binary_migrations.insert(from, migration);
+		self
+	}
+	fn migration_path<M: Copy>(&self, steps: &HashMap<u32, M>, from: u32) -> Result<Vec<(u32, M)>, MigrationError> {
+		if from > self.version {
+			return Err(MigrationError::Newer { component: self.name, found: from, current: self.version });
+		}
+		(from..self.version).map(|v| {
+			steps.get(&v).map(|m| (v, *m)).ok_or(MigrationError::Missing { component: self.name, from: v, to: v + 1 })
+		}).collect()
+	}
+	pub fn migrate_json(&self, column: Vec<Value>, from: u32) -> Result<Vec<Value>, MigrationError> {
+		let mut column = column;
+		for (v, step) in self.migration_path(&self.json_migrations, from)? {
+			column = column.into_iter()
+				.map(step)
+				.collect::<Result<_, _>>()
+				.map_err(|message| MigrationError::Failed { component: self.name, from: v, message })?;
+		}
+		Ok(column)
+	}
+	pub fn migrate_binary(&self, column: Vec<u8>, count: usize, from: u32) -> Result<Vec<u8>, MigrationError> {
+		let mut column = column;
+		for (v, step) in self.migration_path(&self.binary_migrations, from)? {
+			column = step(&column, count).map_err(|message| MigrationError::Failed { component: self.name, from: v, message })?;
+		}
+		Ok(column)
+	}
+	pub fn debug(&self, cv: &ComponentVec, row: usize) -> Option<String> {
+		self.debug.map(|f| f(cv, row))
+	}
+	pub fn eq(&self, a: &ComponentVec, a_row: usize, b: &ComponentVec, b_row: usize) -> Option<bool> {
+		self.eq.map(|f| f(a, a_row, b, b_row))
+	}
+	pub fn clone_into(&self, src: &ComponentVec, row: usize, dst: &mut ComponentVec) -> bool {
+		if let Some(f) = self.clone {
+			f(src, row, dst);
+			true
+		} else {
+			false
+		}
+	}
+	pub fn default_into(&self, dst: &mut ComponentVec) -> bool {
+		if let Some(f) = self.default {
+			f(dst);
+			true
+		} else {
+			false
+		}
+	}
+	pub fn serialize(&self, cv: &ComponentVec, row: usize) -> Option<serde_json::Result<Value>> {
+		self.serialize.map(|f| f(cv, row))
+	}
+	pub fn deserialize_into(&self, value: Value, dst: &mut ComponentVec) -> Option<serde_json::Result<()>> {
+		self.deserialize.map(|f| f(value, dst))
+	}
+	pub fn stable_hash(&self, cv: &ComponentVec, row: usize, state: &mut StableHasher) {
+		if let Some(f) = self.hash {
+			f(cv, row, state);
+		}
+	}
+	pub fn encode(&self, cv: &ComponentVec, out: &mut Vec<u8>) {
+		if let Some(f) = self.encode {
+			f(cv, out);
+		}
+	}
+	pub fn decode_into(&self, input: &mut BinaryReader, count: usize, dst: &mut ComponentVec) -> Option<Result<(), BinaryError>> {
+		self.decode.map(|f| f(input, count, dst))
+	}
+	pub fn map_entities(&self, cv: &mut ComponentVec, row: usize, map: &EntityMap) {
+		if let Some(f) = self.map_entities {
+			f(cv, row, map);
+		}
+	}
+}
+
+#[derive(Default)]
+pub struct ComponentRegistry {
+	infos: HashMap<TypeId, ComponentInfo>,
+	names: HashMap<&'static str, TypeId>,
+	stable: HashMap<StableId, TypeId>,
+}
+impl ComponentRegistry {
+	pub fn new() -> Self {
+		Self::default()
+	}
+	pub fn register<T:'static>(&mut self) -> &mut ComponentInfo {
+		let (names, stable) = (&mut self.names, &mut self.stable);
+		self.infos.entry(TypeId::of::<T>()).or_insert_with(|| {
+			let info = ComponentInfo::of::<T>();
+			names.insert(info.name, info.type_id);
+			stable.insert(info.stable_id, info.type_id);
+			info
+		})
+	}
+	pub fn register_stable<T:'static>(&mut self, id: impl Into<StableId>) -> &mut ComponentInfo {
+		let id = id.into();
+		let old = self.register::<T>().stable_id;
+		if let Some(other) = self.stable.get(&id).and_then(|tid| self.infos.get(tid)) {
+			assert!(other.type_id == TypeId::of::<T>(), "Stable id {:?} already used by {}", id, other.name);
+		}
+		self.stable.remove(&old);
+		self.stable.insert(id, TypeId::of::<T>());
+		let info = self.infos.get_mut(&TypeId::of::<T>()).unwrap();
+		info.stable_id = id;
+		info
+	}
+	pub fn contains(&self, tid: &TypeId) -> bool {
+		self.infos.contains_key(tid)
+	}
+	pub fn get(&self, tid: &TypeId) -> Option<&ComponentInfo> {
+		self.infos.get(tid)
+	}
+	pub(crate) fn mark_tabled(&mut self, tid: &TypeId) {
+		if let Some(info) = self.infos.get_mut(tid) {
+			info.tabled = true;
+		}
+	}
+	pub fn get_by_name(&self, name: &str) -> Option<&ComponentInfo> {
+		self.names.get(name).and_then(|tid| self.infos.get(tid))
+	}
+	pub fn get_by_stable(&self, id: StableId) -> Option<&ComponentInfo> {
+		self.stable.get(&id).and_then(|tid| self.infos.get(tid))
+	}
+	pub fn iter(&self) -> impl Iterator<Item = &ComponentInfo> {
+		self.infos.values()
+	}
+}
@@ -0,0 +1,114 @@
+use hashbrown::HashSet;
+
+use crate::{EntityId, StableId, ECS};
+
+// decides what a single client gets to see, consulted by its `Replicator` on every collect
+pub trait Relevance {
+	fn is_relevant(&self, ecs: &ECS, entity: EntityId) -> bool;
+	fn is_component_relevant(&self, _ecs: &ECS, _entity: EntityId, _component: StableId) -> bool {
+		true
+	}
+}
+
+#[derive(Default, Clone, Copy, Debug)]
+pub struct Everything;
+impl Relevance for Everything {
+	fn is_relevant(&self, _ecs: &ECS, _entity: EntityId) -> bool {
+		true
+	}
+}
+
+impl<F: Fn(&ECS, EntityId) -> bool> Relevance for F {
+	fn is_relevant(&self, ecs: &ECS, entity: EntityId) -> bool {
+		self(ecs, entity)
+	}
+}
+
+#[derive(Default, Clone, Debug)]
+pub struct VisibilitySet {
+	entities: HashSet<EntityId>,
+	hidden_components: HashSet<StableId>,
+}
+impl VisibilitySet {
+	pub fn new() -> Self {
+		Self::default()
+	}
+	pub fn insert(&mut self, entity: EntityId) -> bool {
+		self.entities.insert(entity)
+	}
+	pub fn remove(&mut self, entity: EntityId) -> bool {
+		self.entities.remove(&entity)
+	}
+	pub fn contains(&self, entity: EntityId) -> bool {
+		self.entities.contains(&entity)
+	}
+	pub fn hide_component(&mut self, component: StableId) {
+		self.hidden_components.insert(component);
+	}
+	pub fn show_component(&mut self, component: StableId) {
+		self.hidden_components.remove(&component);
+	}
+}
+impl Relevance for VisibilitySet {
+	fn is_relevant(&self, _ecs: &ECS, entity: EntityId) -> bool {
+		self.contains(entity)
+	}
+	fn is_component_relevant(&self, _ecs: &ECS, _entity: EntityId, component: StableId) -> bool {
+		!self.hidden_components.contains(&component)
+	}
+}
+
+// entities without a `T` to measure are never in range
+pub struct Distance<T> {
+	pub origin: [f32; 3],
+	pub radius: f32,
+	position: fn(&T) -> [f32; 3],
+}
+impl<T> Distance<T> {
+	pub fn new(origin: [f32; 3], radius: f32, position: fn(&T) -> [f32; 3]) -> Self {
+		Self { origin, radius, position }
+	}
+}
+impl<T: 'static> Relevance for Distance<T> {
+	fn is_relevant(&self, ecs: &ECS, entity: EntityId) -> bool {
+		ecs.get_component::<T>(entity).is_some_and(|c| {
+			let p = (self.position)(c);
+			let d: f32 = (0..3).map(|i| (p[i] - self.origin[i]).powi(2)).sum();
+			d <= self.radius * self.radius
+		})
+	}
+}
+
+// entities carrying a `T` equal to the client's, entities without one are never shown
+pub struct SameTeam<T>(pub T);
+impl<T: 'static + PartialEq> Relevance for SameTeam<T> {
+	fn is_relevant(&self, ecs: &ECS, entity: EntityId) -> bool {
+		ecs.get_component::<T>(entity) == Some(&self.0)
+	}
+}
+
+pub struct AnyOf(pub Vec<Box<dyn Relevance>>);
+impl Relevance for AnyOf {
+	fn is_relevant(&self, ecs: &ECS, entity: EntityId) -> bool {
+		self.0.iter().any(|r| r.is_relevant(ecs, entity))
+	}
+	fn is_component_relevant(&self, ecs: &ECS, entity: EntityId, component: StableId) -> bool {
+		self.0.iter().any(|r| r.is_relevant(ecs, entity) && r.is_component_relevant(ecs, entity, component))
+	}
+}
+
+pub struct AllOf(pub Vec<Box<dyn Relevance>>);
+impl Relevance for AllOf {
+	fn is_relevant(&self, ecs: &ECS, entity: EntityId) -> bool {
+		self.0.iter().all(|r| r.is_relevant(ecs, entity))
+	}
+	fn is_component_relevant(&self, ecs: &ECS, entity: EntityId, component: StableId) -> bool {
+		self.0.iter().all(|r| r.is_component_relevant(ecs, entity, component))
+	}
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RelevanceEvent {
+	Enter(EntityId),
+	Leave(EntityId),
+}
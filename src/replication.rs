@@ -0,0 +1,225 @@
+use std::fmt;
+
+use hashbrown::{HashMap, HashSet};
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+
+use crate::{component_vec::OwnedColumn, relevance::{Everything, Relevance, RelevanceEvent}, EntityId, EntityMap, StableId, ECS};
+
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq)]
+pub struct Delta {
+	pub tick: u32,
+	pub spawned: Vec<EntityId>,
+	pub despawned: Vec<EntityId>,
+	pub inserted: Vec<(EntityId, StableId, Value)>,
+	pub changed: Vec<(EntityId, StableId, Value)>,
+	pub removed: Vec<(EntityId, StableId)>,
+}
+impl Delta {
+	pub fn is_empty(&self) -> bool {
+		self.spawned.is_empty() && self.despawned.is_empty() && self.inserted.is_empty()
+			&& self.changed.is_empty() && self.removed.is_empty()
+	}
+	pub fn to_bytes(&self) -> Result<Vec<u8>, ReplicationError> {
+		Ok(serde_json::to_vec(self)?)
+	}
+	pub fn from_bytes(bytes: &[u8]) -> Result<Self, ReplicationError> {
+		Ok(serde_json::from_slice(bytes)?)
+	}
+}
+
+#[derive(Debug)]
+pub enum ReplicationError {
+	UnknownComponent(StableId),
+	UnknownEntity(EntityId),
+	Journaling,
+	Serde(serde_json::Error),
+}
+impl fmt::Display for ReplicationError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			ReplicationError::UnknownComponent(id) => write!(f, "no replicated component registered as {:?}", id),
+			ReplicationError::UnknownEntity(e) => write!(f, "entity {:?} was never replicated", e),
+			ReplicationError::Journaling => write!(f, "deltas can't be applied while a journal is recording"),
+			ReplicationError::Serde(e) => write!(f, "{}", e),
+		}
+	}
+}
+impl std::error::Error for ReplicationError {}
+impl From<serde_json::Error> for ReplicationError {
+	fn from(e: serde_json::Error) -> Self {
+		ReplicationError::Serde(e)
+	}
+}
+
+// server side, remembers what the other end has already been sent
+pub struct Replicator<R = Everything> {
+	last_tick: Option<u32>,
+	known: HashMap<EntityId, HashSet<StableId>>,
+	relevance: R,
+	events: Vec<RelevanceEvent>,
+}
+impl Default for Replicator {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+impl Replicator {
+	pub fn new() -> Self {
+		Self::with_relevance(Everything)
+	}
+}
+impl<R: Relevance> Replicator<R> {
+	pub fn with_relevance(relevance: R) -> Self {
+		Self {
+			last_tick: None,
+			known: HashMap::new(),
+			relevance,
+			events: Vec::new(),
+		}
+	}
+	pub fn relevance(&self) -> &R {
+		&self.relevance
+	}
+	pub fn relevance_mut(&mut self) -> &mut R {
+		&mut self.relevance
+	}
+	pub fn last_tick(&self) -> Option<u32> {
+		self.last_tick
+	}
+	pub fn is_known(&self, entity: EntityId) -> bool {
+		self.known.contains_key(&entity)
+	}
+	// entering covers fresh spawns too, leaving only entities that are still alive
+	pub fn drain_events(&mut self) -> impl Iterator<Item = RelevanceEvent> + '_ {
+		self.events.drain(..)
+	}
+	// only relevant entities holding at least one replicated component exist on the other end.
+	// the tick isn't advanced here, rows written after a collect in the same tick carry that
+	// tick, so rows stamped with the last collected tick are sent again rather than missed
+	pub fn collect(&mut self, ecs: &ECS) -> Result<Delta, ReplicationError> {
+		let mut delta = Delta { tick: ecs.change_tick(), ..Default::default() };
+		let mut seen = HashMap::new();
+		for (_, arche) in ecs.archetypes.iter() {
+			if arche.len() == 0 {
+				continue;
+			}
+			let mut infos: Vec<_> = arche.types()
+				.filter_map(|tid| ecs.registry.get(tid))
+				.filter(|info| info.is_replicated())
+				.collect();
+			if infos.is_empty() {
+				continue;
+			}
+			infos.sort_by_key(|info| info.stable_id());
+			for (row, id) in arche.get_component_vec::<EntityId>().enumerate() {
+				if !self.relevance.is_relevant(ecs, *id) {
+					continue;
+				}
+				let visible: Vec<_> = infos.iter()
+					.filter(|info| self.relevance.is_component_relevant(ecs, *id, info.stable_id()))
+					.collect();
+				if visible.is_empty() {
+					continue;
+				}
+				let known = self.known.get(id);
+				if known.is_none() {
+					delta.spawned.push(*id);
+					self.events.push(RelevanceEvent::Enter(*id));
+				}
+				let mut types = HashSet::with_capacity(visible.len());
+				for info in visible {
+					let cv = arche.get_column(&info.type_id()).unwrap();
+					types.insert(info.stable_id());
+					if !known.is_some_and(|k| k.contains(&info.stable_id())) {
+						delta.inserted.push((*id, info.stable_id(), info.serialize(cv, row).unwrap()?));
+					} else if self.last_tick.is_none_or(|tick| cv.changed_tick(row) >= tick) {
+						delta.changed.push((*id, info.stable_id(), info.serialize(cv, row).unwrap()?));
+					}
+				}
+				if let Some(known) = known {
+					let mut removed: Vec<_> = known.difference(&types).copied().collect();
+					removed.sort();
+					delta.removed.extend(removed.into_iter().map(|sid| (*id, sid)));
+				}
+				seen.insert(*id, types);
+			}
+		}
+		delta.despawned = self.known.keys().filter(|id| !seen.contains_key(*id)).copied().collect();
+		delta.despawned.sort_by_key(|id| id.0);
+		for id in delta.despawned.iter() {
+			if ecs.contains_entity(*id) {
+				self.events.push(RelevanceEvent::Leave(*id));
+			}
+		}
+		self.known = seen;
+		self.last_tick = Some(delta.tick);
+		Ok(delta)
+	}
+}
+
+// client side, translates the sender's entity ids into local ones
+#[derive(Default)]
+pub struct Replica {
+	map: EntityMap,
+}
+impl Replica {
+	pub fn new() -> Self {
+		Self::default()
+	}
+	pub fn map(&self) -> &EntityMap {
+		&self.map
+	}
+	pub fn local(&self, remote: EntityId) -> Option<EntityId> {
+		self.map.get(&remote)
+	}
+	pub fn apply(&mut self, ecs: &mut ECS, delta: &Delta) -> Result<(), ReplicationError> {
+		if ecs.is_journaling() {
+			return Err(ReplicationError::Journaling);
+		}
+		let mut map = self.map.clone();
+		let spawned: Vec<EntityId> = delta.spawned.iter().map(|remote| {
+			let local = EntityId(ecs.entity_ids.next());
+			map.insert(*remote, local);
+			local
+		}).collect();
+
+		// decode everything up front so a bad delta leaves the world untouched
+		let mut staged = Vec::with_capacity(delta.inserted.len() + delta.changed.len());
+		for (remote, id, value) in delta.inserted.iter().chain(delta.changed.iter()) {
+			let info = ecs.registry.get_by_stable(*id)
+				.filter(|info| info.is_replicated())
+				.ok_or(ReplicationError::UnknownComponent(*id))?;
+			let local = map.get(remote).ok_or(ReplicationError::UnknownEntity(*remote))?;
+			let mut column = OwnedColumn { data: info.new_column(), drop: info.drop_fn() };
+			info.deserialize_into(value.clone(), &mut column.data).unwrap()?;
+			info.map_entities(&mut column.data, 0, &map);
+			staged.push((local, column));
+		}
+		let mut removed = Vec::with_capacity(delta.removed.len());
+		for (remote, id) in delta.removed.iter() {
+			let info = ecs.registry.get_by_stable(*id).ok_or(ReplicationError::UnknownComponent(*id))?;
+			removed.push((map.get(remote).ok_or(ReplicationError::UnknownEntity(*remote))?, info.type_id()));
+		}
+
+		for local in spawned {
+			ecs.spawn_with_id(local);
+		}
+		// values for entities that are already gone are dropped with their column
+		for (local, column) in staged {
+			if ecs.contains_entity(local) {
+				ecs.attach_column(local, &mut column.into_inner());
+			}
+		}
+		for (local, tid) in removed {
+			ecs.remove_type(local, &tid);
+		}
+		for remote in delta.despawned.iter() {
+			if let Some(local) = map.remove(remote) {
+				ecs.despawn(local);
+			}
+		}
+		self.map = map;
+		Ok(())
+	}
+}
@@ -0,0 +1,72 @@
+use std::collections::BTreeMap;
+
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Serialize, Deserialize};
+
+use crate::{checksum::{StableHash, StableHasher}, EntityId};
+
+pub type RngStream = ChaCha8Rng;
+
+// forked streams come from a differently salted generator so they never alias the main stream
+const FORK_SALT: u64 = 0x9e3779b97f4a7c15;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WorldRng {
+	seed: u64,
+	main: RngStream,
+	streams: BTreeMap<u64, RngStream>,
+}
+impl WorldRng {
+	pub fn new(seed: u64) -> Self {
+		Self {
+			seed,
+			main: RngStream::seed_from_u64(seed),
+			streams: BTreeMap::new(),
+		}
+	}
+	pub fn from_entropy() -> Self {
+		Self::new(rand::random())
+	}
+	pub fn seed(&self) -> u64 {
+		self.seed
+	}
+	pub fn reseed(&mut self, seed: u64) {
+		*self = Self::new(seed);
+	}
+	pub fn stream(&mut self, key: u64) -> &mut RngStream {
+		let seed = self.seed;
+		self.streams.entry(key).or_insert_with(|| {
+			let mut rng = RngStream::seed_from_u64(seed ^ FORK_SALT);
+			rng.set_stream(key);
+			rng
+		})
+	}
+	pub fn system_stream(&mut self, system: &str) -> &mut RngStream {
+		let mut state = StableHasher::new();
+		system.stable_hash(&mut state);
+		self.stream(state.finish())
+	}
+	pub fn entity_stream(&mut self, entity: EntityId) -> &mut RngStream {
+		let mut state = StableHasher::new();
+		entity.stable_hash(&mut state);
+		self.stream(state.finish())
+	}
+	pub fn drop_stream(&mut self, key: u64) {
+		self.streams.remove(&key);
+	}
+}
+impl RngCore for WorldRng {
+	fn next_u32(&mut self) -> u32 {
+		self.main.next_u32()
+	}
+	fn next_u64(&mut self) -> u64 {
+		self.main.next_u64()
+	}
+	fn fill_bytes(&mut self, dest: &mut [u8]) {
+		self.main.fill_bytes(dest)
+	}
+	fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
+		self.main.try_fill_bytes(dest)
+	}
+}
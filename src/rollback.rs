@@ -0,0 +1,228 @@
+use std::{any::TypeId, collections::VecDeque};
+
+use hashbrown::{HashMap, HashSet};
+
+use crate::{component_vec::OwnedColumn, ids::IdAllocator, shared::AnyPool, ArchetypeId, EntityId, WorldRng, ECS};
+
+pub(crate) struct SnapshotTable {
+	archetype: ArchetypeId,
+	pub(crate) entities: Vec<EntityId>,
+	pub(crate) columns: Vec<OwnedColumn>,
+}
+
+// one sparse set, rows line up with `entities`
+pub(crate) struct SnapshotSparse {
+	pub(crate) entities: Vec<EntityId>,
+	pub(crate) column: OwnedColumn,
+}
+
+pub struct Snapshot {
+	pub(crate) alive: HashSet<EntityId>,
+	pub(crate) tables: Vec<SnapshotTable>,
+	pub(crate) sparse: Vec<SnapshotSparse>,
+	// handles only mean something next to the pool they came from
+	shared: HashMap<TypeId, Box<dyn AnyPool>>,
+	rng: WorldRng,
+	entity_ids: IdAllocator,
+}
+impl Snapshot {
+	pub fn entity_count(&self) -> usize {
+		self.alive.len()
+	}
+}
+
+impl ECS {
+	pub(crate) fn rollback_types(&self) -> HashSet<TypeId> {
+		self.registry.iter().filter(|info| info.is_rollback()).map(|info| info.type_id()).collect()
+	}
+	pub fn snapshot(&self) -> Snapshot {
+		let rollback = self.rollback_types();
+		let mut tables = Vec::new();
+		for (a_id, arche) in self.archetypes.iter() {
+			if arche.len() == 0 {
+				continue;
+			}
+			let columns: Vec<_> = arche.types()
+				.filter(|tid| rollback.contains(*tid))
+				.map(|tid| {
+					let info = self.registry.get(tid).unwrap();
+					OwnedColumn { data: info.rollback_column(arche.get_column(tid).unwrap()), drop: info.drop_fn() }
+				})
+				.collect();
+			if !columns.is_empty() {
+				tables.push(SnapshotTable {
+					archetype: a_id,
+					entities: arche.get_component_vec::<EntityId>().copied().collect(),
+					columns,
+				});
+			}
+		}
+		let sparse = self.sparse.iter()
+			.filter(|(tid, set)| rollback.contains(*tid) && set.len() > 0)
+			.map(|(tid, set)| {
+				let info = self.registry.get(tid).unwrap();
+				SnapshotSparse {
+					entities: set.entities().to_vec(),
+					column: OwnedColumn { data: info.rollback_column(set.column()), drop: info.drop_fn() },
+				}
+			})
+			.collect();
+		Snapshot {
+			alive: self.entity_index.keys().copied().collect(),
+			tables,
+			sparse,
+			shared: self.shared.iter().map(|(tid, pool)| (*tid, pool.clone_pool())).collect(),
+			rng: self.rng.clone(),
+			entity_ids: self.entity_ids.clone(),
+		}
+	}
+	// the journal can't describe a restore, so the two don't mix
+	pub fn restore(&mut self, snapshot: &Snapshot) {
+		assert!(!self.is_journaling(), "Cannot restore a snapshot while a journal is recording");
+		let rollback = self.rollback_types();
+		self.rng = snapshot.rng.clone();
+		// re-simulated frames hand out the same entity ids again
+		self.entity_ids = snapshot.entity_ids.clone();
+		self.shared = snapshot.shared.iter().map(|(tid, pool)| (*tid, pool.clone_pool())).collect();
+
+		let spawned: Vec<EntityId> = self.entities().filter(|e| !snapshot.alive.contains(e)).collect();
+		for e in spawned {
+			self.despawn(e);
+		}
+		// entities without rollback components aren't in any table, they come back empty
+		let mut missing: Vec<EntityId> = snapshot.alive.iter().filter(|e| !self.entity_index.contains_key(*e)).copied().collect();
+		missing.sort_by_key(|e| e.0);
+		for e in missing {
+			self.spawn_with_id(e);
+		}
+
+		let mut restored = HashSet::new();
+		for table in snapshot.tables.iter() {
+			restored.extend(table.entities.iter().copied());
+			// fast path: nothing structural happened to this table, overwrite whole columns
+			let unchanged = self.archetypes.get(&table.archetype)
+				.is_some_and(|arche| arche.get_component_vec::<EntityId>().eq(table.entities.iter()));
+			if unchanged {
+				let arche = self.archetypes.get_mut(&table.archetype).unwrap();
+				for OwnedColumn { data: src, .. } in table.columns.iter() {
+					let info = self.registry.get(&src.type_id()).unwrap();
+					let mut column = info.rollback_column(src);
+					column.mark_all_changed(self.change_tick);
+					let dst = arche.get_column_mut(&src.type_id()).unwrap();
+					unsafe { dst.drop_elements(info.drop_fn()) };
+					dst.append(&mut column);
+				}
+				continue;
+			}
+			for (row, e) in table.entities.iter().enumerate() {
+				self.detach_rollback_except(*e, &rollback, &table.columns);
+				for OwnedColumn { data: src, .. } in table.columns.iter() {
+					let info = self.registry.get(&src.type_id()).unwrap();
+					let mut column = info.new_column();
+					info.rollback_row(src, row, &mut column);
+					self.attach_column(*e, &mut column);
+				}
+			}
+		}
+
+		// survivors that picked up rollback components since the snapshot lose them again
+		let stale: Vec<EntityId> = self.entities().filter(|e| !restored.contains(e)).collect();
+		for e in stale {
+			self.detach_rollback_except(e, &rollback, &[]);
+		}
+
+		// sparse sets are restored on their own, rows that weren't in the snapshot go first
+		let sparse: Vec<TypeId> = rollback.iter().filter(|tid| self.is_sparse(tid)).copied().collect();
+		for tid in sparse {
+			let saved = snapshot.sparse.iter().find(|set| set.column.data.type_id() == tid);
+			let extra: Vec<EntityId> = self.sparse.get(&tid).map_or_else(Vec::new, |set| set.entities().iter()
+				.filter(|e| !saved.is_some_and(|saved| saved.entities.contains(e)))
+				.copied()
+				.collect());
+			for e in extra {
+				self.remove_type(e, &tid);
+			}
+			let Some(saved) = saved else {
+				continue;
+			};
+			for (row, e) in saved.entities.iter().enumerate() {
+				let info = self.registry.get(&tid).unwrap();
+				let mut column = info.new_column();
+				info.rollback_row(&saved.column.data, row, &mut column);
+				self.attach_column(*e, &mut column);
+			}
+		}
+	}
+	fn detach_rollback_except(&mut self, entity: EntityId, rollback: &HashSet<TypeId>, keep: &[OwnedColumn]) {
+		let extra: Vec<TypeId> = self.component_types(entity).into_iter()
+			.filter(|tid| rollback.contains(tid) && !self.is_sparse(tid) && !keep.iter().any(|col| col.data.type_id() == *tid))
+			.collect();
+		for tid in extra {
+			self.remove_type(entity, &tid);
+		}
+	}
+}
+
+pub struct RollbackBuffer {
+	capacity: usize,
+	frames: VecDeque<(u64, Snapshot)>,
+}
+impl RollbackBuffer {
+	pub fn new(capacity: usize) -> Self {
+		assert!(capacity > 0, "Rollback buffer needs room for at least one frame");
+		Self {
+			capacity,
+			frames: VecDeque::with_capacity(capacity),
+		}
+	}
+	pub fn len(&self) -> usize {
+		self.frames.len()
+	}
+	pub fn is_empty(&self) -> bool {
+		self.frames.is_empty()
+	}
+	pub fn oldest_frame(&self) -> Option<u64> {
+		self.frames.front().map(|(f, _)| *f)
+	}
+	pub fn latest_frame(&self) -> Option<u64> {
+		self.frames.back().map(|(f, _)| *f)
+	}
+	pub fn get(&self, frame: u64) -> Option<&Snapshot> {
+		self.frames.iter().find(|(f, _)| *f == frame).map(|(_, s)| s)
+	}
+	// saving a frame discards any later frames, they belong to a timeline that no longer exists
+	pub fn save(&mut self, frame: u64, ecs: &ECS) {
+		while self.frames.back().is_some_and(|(f, _)| *f >= frame) {
+			self.frames.pop_back();
+		}
+		if self.frames.len() == self.capacity {
+			self.frames.pop_front();
+		}
+		self.frames.push_back((frame, ecs.snapshot()));
+	}
+	pub fn rollback(&mut self, ecs: &mut ECS, frame: u64) -> bool {
+		if let Some(snapshot) = self.get(frame) {
+			ecs.restore(snapshot);
+			while self.frames.back().is_some_and(|(f, _)| *f > frame) {
+				self.frames.pop_back();
+			}
+			true
+		} else {
+			false
+		}
+	}
+	// restores the start of `from` and steps the world up to the start of `to`, re-saving every frame on the way
+	pub fn resimulate<F: FnMut(&mut ECS, u64)>(&mut self, ecs: &mut ECS, from: u64, to: u64, mut step: F) -> bool {
+		if !self.rollback(ecs, from) {
+			return false;
+		}
+		for frame in from..to {
+			if frame != from {
+				self.save(frame, ecs);
+			}
+			step(ecs, frame);
+		}
+		self.save(to, ecs);
+		true
+	}
+}
@@ -0,0 +1,181 @@
+use std::{any::TypeId, fmt};
+
+use serde::{Serialize, Deserialize};
+use serde_json::Value;
+
+use hashbrown::HashSet;
+
+use crate::{component_vec::{ComponentVec, OwnedColumn}, ArchetypeId, EntityId, EntityMap, MigrationError, StableId, ECS};
+
+#[derive(Serialize, Deserialize, Default, Clone, Debug, PartialEq)]
+pub struct Scene {
+	pub archetypes: Vec<SceneArchetype>,
+}
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SceneArchetype {
+	pub id: ArchetypeId,
+	pub components: Vec<StableId>,
+	#[serde(default)]
+	pub versions: Vec<u32>,
+	pub entities: Vec<SceneEntity>,
+}
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct SceneEntity {
+	pub id: EntityId,
+	pub components: Vec<Value>,
+}
+impl Scene {
+	pub fn entity_count(&self) -> usize {
+		self.archetypes.iter().map(|a| a.entities.len()).sum()
+	}
+	pub fn to_json(&self) -> Result<String, SceneError> {
+		Ok(serde_json::to_string_pretty(self)?)
+	}
+	pub fn from_json(json: &str) -> Result<Self, SceneError> {
+		Ok(serde_json::from_str(json)?)
+	}
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+	UnknownComponent(StableId),
+	NotSerializable(String),
+	ComponentCount { entity: EntityId, expected: usize, found: usize },
+	DuplicateEntity(EntityId),
+	DuplicateComponent(StableId),
+	Journaling,
+	Migration(MigrationError),
+	Serde(serde_json::Error),
+}
+impl fmt::Display for SceneError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			SceneError::UnknownComponent(id) => write!(f, "no component registered as {:?}", id),
+			SceneError::NotSerializable(name) => write!(f, "component `{}` has no serde vtable", name),
+			SceneError::ComponentCount { entity, expected, found } =>
+				write!(f, "entity {:?} has {} components, archetype lists {}", entity, found, expected),
+			SceneError::DuplicateEntity(e) => write!(f, "entity {:?} appears more than once", e),
+			SceneError::DuplicateComponent(id) => write!(f, "component {:?} is listed twice in one archetype", id),
+			SceneError::Journaling => write!(f, "scenes can't be loaded while a journal is recording"),
+			SceneError::Migration(e) => write!(f, "{}", e),
+			SceneError::Serde(e) => write!(f, "{}", e),
+		}
+	}
+}
+impl std::error::Error for SceneError {}
+impl From<MigrationError> for SceneError {
+	fn from(e: MigrationError) -> Self {
+		SceneError::Migration(e)
+	}
+}
+impl From<serde_json::Error> for SceneError {
+	fn from(e: serde_json::Error) -> Self {
+		SceneError::Serde(e)
+	}
+}
+
+impl ECS {
+	pub fn save_scene(&self) -> Result<Scene, SceneError> {
+		let mut scene = Scene::default();
+		for (a_id, arche) in self.archetypes.iter() {
+			if arche.len() == 0 {
+				continue;
+			}
+			let mut types: Vec<_> = arche.types()
+				.filter(|tid| **tid != TypeId::of::<EntityId>())
+				.filter_map(|tid| self.registry.get(tid))
+				.filter(|info| info.has_serde())
+				.collect();
+			types.sort_by_key(|info| info.stable_id());
+
+			let ids = arche.get_component_vec::<EntityId>();
+			let mut entities = Vec::with_capacity(arche.len());
+			for (row, id) in ids.enumerate() {
+				let mut components = Vec::with_capacity(types.len());
+				for info in types.iter() {
+					let cv = arche.get_column(&info.type_id()).unwrap();
+					components.push(info.serialize(cv, row).unwrap()?);
+				}
+				entities.push(SceneEntity { id: *id, components });
+			}
+			scene.archetypes.push(SceneArchetype {
+				id: a_id,
+				components: types.iter().map(|info| info.stable_id()).collect(),
+				versions: types.iter().map(|info| info.version()).collect(),
+				entities,
+			});
+		}
+		Ok(scene)
+	}
+	pub fn load_scene(&mut self, scene: &Scene) -> Result<EntityMap, SceneError> {
+		if self.is_journaling() {
+			return Err(SceneError::Journaling);
+		}
+		let mut seen = HashSet::new();
+		for s_arche in scene.archetypes.iter() {
+			for s_entity in s_arche.entities.iter() {
+				if !seen.insert(s_entity.id) {
+					return Err(SceneError::DuplicateEntity(s_entity.id));
+				}
+			}
+			let mut components = HashSet::new();
+			for id in s_arche.components.iter() {
+				if !components.insert(*id) {
+					return Err(SceneError::DuplicateComponent(*id));
+				}
+			}
+		}
+		let mut map = EntityMap::new();
+		for s_arche in scene.archetypes.iter() {
+			for s_entity in s_arche.entities.iter() {
+				map.insert(s_entity.id, EntityId(self.entity_ids.next()));
+			}
+		}
+
+		// stage everything first so a bad scene leaves the world untouched
+		let mut staged: Vec<(EntityId, Vec<OwnedColumn>)> = Vec::with_capacity(map.len());
+		for s_arche in scene.archetypes.iter() {
+			let mut infos = Vec::with_capacity(s_arche.components.len());
+			for id in s_arche.components.iter() {
+				match self.registry.get_by_stable(*id) {
+					Some(info) if info.has_serde() => infos.push(info),
+					Some(info) => return Err(SceneError::NotSerializable(info.name().to_string())),
+					None => return Err(SceneError::UnknownComponent(*id)),
+				}
+			}
+			for s_entity in s_arche.entities.iter() {
+				if s_entity.components.len() != infos.len() {
+					return Err(SceneError::ComponentCount {
+						entity: s_entity.id,
+						expected: infos.len(),
+						found: s_entity.components.len(),
+					});
+				}
+			}
+			// bring each column up to the current schema before building any components from it
+			let mut values = Vec::with_capacity(infos.len());
+			for (i, info) in infos.iter().enumerate() {
+				let column = s_arche.entities.iter().map(|e| e.components[i].clone()).collect();
+				let version = s_arche.versions.get(i).copied().unwrap_or(0);
+				values.push(info.migrate_json(column, version)?.into_iter());
+			}
+			for s_entity in s_arche.entities.iter() {
+				let mut columns = Vec::with_capacity(infos.len());
+				for (info, column) in infos.iter().zip(values.iter_mut()) {
+					let mut cv = OwnedColumn { data: info.new_column(), drop: info.drop_fn() };
+					info.deserialize_into(column.next().unwrap(), &mut cv.data).unwrap()?;
+					info.map_entities(&mut cv.data, 0, &map);
+					columns.push(cv);
+				}
+				staged.push((map.map(s_entity.id), columns));
+			}
+		}
+
+		for (eid, columns) in staged {
+			let mut columns: Vec<ComponentVec> = columns.into_iter().map(OwnedColumn::into_inner).collect();
+			self.spawn_with_id(eid);
+			self.insert_columns(eid, &mut columns);
+		}
+		Ok(map)
+	}
+}
@@ -0,0 +1,189 @@
+use std::{any::{Any, TypeId}, fmt, hash::{BuildHasher, Hash, Hasher}, marker::PhantomData};
+
+use hashbrown::{hash_map::DefaultHashBuilder, HashMap};
+
+use crate::{EntityId, StableHash, StableHasher, ECS};
+
+// handle into a per-type pool of deduplicated values, stored on entities like any other component.
+// the generation tells a handle to a pruned value apart from whatever reused its slot.
+// handles are only meaningful next to their pool, so they have no serde impls
+pub struct Shared<T> {
+	index: u32,
+	generation: u32,
+	marker: PhantomData<fn() -> T>,
+}
+impl<T> Shared<T> {
+	fn new(index: usize, generation: u32) -> Self {
+		Self { index: index as u32, generation, marker: PhantomData }
+	}
+	pub fn index(&self) -> usize {
+		self.index as usize
+	}
+	pub fn generation(&self) -> u32 {
+		self.generation
+	}
+}
+impl<T> Clone for Shared<T> {
+	fn clone(&self) -> Self {
+		*self
+	}
+}
+impl<T> Copy for Shared<T> {}
+impl<T> PartialEq for Shared<T> {
+	fn eq(&self, other: &Self) -> bool {
+		(self.index, self.generation) == (other.index, other.generation)
+	}
+}
+impl<T> Eq for Shared<T> {}
+impl<T> Hash for Shared<T> {
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		self.index.hash(state);
+		self.generation.hash(state);
+	}
+}
+impl<T> fmt::Debug for Shared<T> {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "Shared({}v{})", self.index, self.generation)
+	}
+}
+impl<T> StableHash for Shared<T> {
+	fn stable_hash(&self, state: &mut StableHasher) {
+		self.index.stable_hash(state);
+		self.generation.stable_hash(state);
+	}
+}
+
+#[derive(Clone)]
+struct Slot<T> {
+	value: Option<T>,
+	generation: u32,
+	hash: u64,
+}
+
+// pruned values leave a tombstone behind, slots are reused but never renumbered
+#[derive(Clone)]
+pub(crate) struct SharedPool<T> {
+	slots: Vec<Slot<T>>,
+	free: Vec<u32>,
+	// occupied slots by the hash of their value, so interning doesn't compare against every value
+	lookup: HashMap<u64, Vec<u32>>,
+	hasher: DefaultHashBuilder,
+}
+impl<T: Hash + Eq> SharedPool<T> {
+	fn intern(&mut self, value: T) -> Shared<T> {
+		let hash = self.hasher.hash_one(&value);
+		let slots = &self.slots;
+		if let Some(index) = self.lookup.get(&hash).and_then(|bucket| bucket.iter().find(|i| slots[**i as usize].value.as_ref() == Some(&value))) {
+			return Shared::new(*index as usize, slots[*index as usize].generation);
+		}
+		let index = if let Some(index) = self.free.pop() {
+			let slot = &mut self.slots[index as usize];
+			slot.value = Some(value);
+			slot.hash = hash;
+			index
+		} else {
+			self.slots.push(Slot { value: Some(value), generation: 0, hash });
+			(self.slots.len() - 1) as u32
+		};
+		self.lookup.entry(hash).or_default().push(index);
+		Shared::new(index as usize, self.slots[index as usize].generation)
+	}
+}
+impl<T> SharedPool<T> {
+	fn new() -> Self {
+		Self { slots: Vec::new(), free: Vec::new(), lookup: HashMap::new(), hasher: DefaultHashBuilder::default() }
+	}
+	fn get(&self, handle: Shared<T>) -> Option<&T> {
+		self.slots.get(handle.index())
+			.filter(|slot| slot.generation == handle.generation)
+			.and_then(|slot| slot.value.as_ref())
+	}
+}
+
+// type erased pool, cloned into snapshots
+pub(crate) trait AnyPool {
+	fn as_any(&self) -> &dyn Any;
+	fn as_any_mut(&mut self) -> &mut dyn Any;
+	fn clone_pool(&self) -> Box<dyn AnyPool>;
+}
+impl<T:'static + Clone> AnyPool for SharedPool<T> {
+	fn as_any(&self) -> &dyn Any {
+		self
+	}
+	fn as_any_mut(&mut self) -> &mut dyn Any {
+		self
+	}
+	fn clone_pool(&self) -> Box<dyn AnyPool> {
+		Box::new(self.clone())
+	}
+}
+
+impl ECS {
+	fn shared_pool<T:'static>(&self) -> Option<&SharedPool<T>> {
+		self.shared.get(&TypeId::of::<T>()).and_then(|pool| pool.as_any().downcast_ref())
+	}
+	fn shared_pool_mut<T:'static + Clone>(&mut self) -> &mut SharedPool<T> {
+		self.shared.entry(TypeId::of::<T>())
+			.or_insert_with(|| Box::new(SharedPool::<T>::new()))
+			.as_any_mut()
+			.downcast_mut()
+			.unwrap()
+	}
+	// equal values end up behind the same handle
+	pub fn attach_shared<T:'static + Hash + Eq + Clone>(&mut self, entity: EntityId, value: T) -> Shared<T> {
+		let handle = self.shared_pool_mut::<T>().intern(value);
+		self.attach_component(entity, handle);
+		handle
+	}
+	pub fn detach_shared<T:'static>(&mut self, entity: EntityId) {
+		self.detach_component::<Shared<T>>(entity);
+	}
+	// None once the value has been pruned, even if its slot holds something else by now
+	pub fn shared<T:'static>(&self, handle: Shared<T>) -> Option<&T> {
+		self.shared_pool::<T>()?.get(handle)
+	}
+	pub fn get_shared<T:'static>(&self, entity: EntityId) -> Option<&T> {
+		self.shared(*self.get_component::<Shared<T>>(entity)?)
+	}
+	pub fn shared_count<T:'static>(&self) -> usize {
+		self.shared_pool::<T>().map_or(0, |pool| pool.slots.len() - pool.free.len())
+	}
+	// one entry per distinct value in use, in pool order, e.g. one draw call per mesh
+	pub fn iter_shared_groups<T:'static>(&self) -> impl Iterator<Item = (&T, Vec<EntityId>)> + '_ {
+		let slots = self.shared_pool::<T>().map(|pool| pool.slots.as_slice()).unwrap_or(&[]);
+		let mut groups: Vec<Vec<EntityId>> = slots.iter().map(|_| Vec::new()).collect();
+		for (e, handle) in crate::query!(self, Shared<T>) {
+			if slots.get(handle.index()).is_some_and(|slot| slot.generation == handle.generation) {
+				groups[handle.index()].push(e);
+			}
+		}
+		slots.iter().zip(groups)
+			.filter(|(_, group)| !group.is_empty())
+			.filter_map(|(slot, group)| Some((slot.value.as_ref()?, group)))
+	}
+	// drops values no entity refers to anymore, handles to the remaining ones stay as they are
+	pub fn prune_shared<T:'static>(&mut self) -> usize {
+		let Some(count) = self.shared_pool::<T>().map(|pool| pool.slots.len()) else {
+			return 0;
+		};
+		let mut used = vec![false; count];
+		for (_, handle) in crate::query!(self, Shared<T>) {
+			if let Some(used) = used.get_mut(handle.index()) {
+				*used = true;
+			}
+		}
+		let pool: &mut SharedPool<T> = self.shared.get_mut(&TypeId::of::<T>()).unwrap().as_any_mut().downcast_mut().unwrap();
+		let mut pruned = 0;
+		for (index, slot) in pool.slots.iter_mut().enumerate() {
+			if !used[index] && slot.value.take().is_some() {
+				slot.generation += 1;
+				pool.free.push(index as u32);
+				if let Some(bucket) = pool.lookup.get_mut(&slot.hash) {
+					bucket.retain(|i| *i as usize != index);
+				}
+				pruned += 1;
+			}
+		}
+		pruned
+	}
+}
@@ -0,0 +1,75 @@
+use hashbrown::HashMap;
+
+use crate::{component_vec::ComponentVec, EntityId};
+
+// storage for one component type outside the archetypes, attaching or detaching never moves the entity
+pub(crate) struct SparseSet {
+	dense: ComponentVec,
+	entities: Vec<EntityId>,
+	index: HashMap<EntityId, usize>,
+}
+impl SparseSet {
+	pub fn new(dense: ComponentVec) -> Self {
+		Self {
+			dense,
+			entities: Vec::new(),
+			index: HashMap::new(),
+		}
+	}
+	pub fn len(&self) -> usize {
+		self.entities.len()
+	}
+	pub fn contains(&self, entity: EntityId) -> bool {
+		self.index.contains_key(&entity)
+	}
+	pub fn row(&self, entity: EntityId) -> Option<usize> {
+		self.index.get(&entity).copied()
+	}
+	pub fn entities(&self) -> &[EntityId] {
+		&self.entities
+	}
+	pub fn column(&self) -> &ComponentVec {
+		&self.dense
+	}
+	pub fn column_mut(&mut self) -> &mut ComponentVec {
+		&mut self.dense
+	}
+	pub fn shrink_to_fit(&mut self) {
+		self.dense.shrink_to_fit();
+		self.entities.shrink_to_fit();
+		self.index.shrink_to_fit();
+	}
+	pub fn insert(&mut self, entity: EntityId, column: &mut ComponentVec, drop: Option<unsafe fn(*mut u8)>, tick: u32) {
+		let row = if let Some(row) = self.row(entity) {
+			if let Some(drop) = drop {
+				unsafe { drop(self.dense.get_ptr(row)) };
+			}
+			self.dense.replace_with_tail(row, column);
+			row
+		} else {
+			self.dense.adopt_tail(column);
+			self.entities.push(entity);
+			self.index.insert(entity, self.entities.len() - 1);
+			self.entities.len() - 1
+		};
+		self.dense.mark_changed(row, tick);
+	}
+	// without a drop fn the value is forgotten, whoever took it out is responsible for it
+	pub fn remove(&mut self, entity: EntityId, drop: Option<unsafe fn(*mut u8)>) -> bool {
+		let Some(row) = self.index.remove(&entity) else {
+			return false;
+		};
+		unsafe { self.dense.swap_drop(row, drop) };
+		self.entities.swap_remove(row);
+		if let Some(moved) = self.entities.get(row) {
+			self.index.insert(*moved, row);
+		}
+		true
+	}
+	pub fn take(&mut self, entity: EntityId) -> Option<ComponentVec> {
+		let row = self.row(entity)?;
+		let column = unsafe { self.dense.copy_row(row) };
+		self.remove(entity, None);
+		Some(column)
+	}
+}
@@ -4,7 +4,7 @@ trait Letter {
 	fn val(&self) -> char;
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 struct A(usize);
 impl Letter for A {
 	fn val(&self) -> char {
@@ -74,4 +74,1322 @@ fn iter_cast() {
 
 	let x = iter_components_cast!(ecs, [A, C] as Letter).collect::<Vec<&dyn Letter>>();
 	let _x = &x[..];
-}
\ No newline at end of file
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Health(u32);
+
+#[test]
+fn registry() {
+	let (mut ecs, entities) = basic_ecs();
+	let e = create_entity_from!(ecs, [Health(7)]);
+	ecs.registry_mut().register::<Health>().with_debug::<Health>().with_clone::<Health>().with_default::<Health>();
+
+	let tid = TypeId::of::<Health>();
+	let info = ecs.registry().get_by_name(std::any::type_name::<Health>()).unwrap();
+	assert_eq!((info.type_id(), info.size(), info.align()), (tid, 4, 4));
+	assert!(info.drop_fn().is_none());
+	assert!(ecs.registry().get(&TypeId::of::<A>()).is_some_and(|i| !i.has_debug()));
+
+	assert_eq!(ecs.debug_component(e, &tid).as_deref(), Some("Health(7)"));
+	assert!(ecs.clone_component(e, entities[1], &tid));
+	assert!(ecs.attach_default(entities[0], &tid));
+	assert_eq!(ecs.get_component::<Health>(entities[1]), Some(&Health(7)));
+	assert_eq!(ecs.get_component::<A>(entities[1]), Some(&A(5)));
+	assert_eq!(ecs.get_component::<Health>(entities[0]), Some(&Health(0)));
+	assert_eq!(ecs.get_component::<B>(entities[0]), Some(&B(-5.0)));
+	assert!(!ecs.attach_default(entities[2], &TypeId::of::<A>()));
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Serialize, serde::Deserialize)]
+struct Parent(EntityId);
+impl MapEntities for Parent {
+	fn map_entities(&mut self, map: &EntityMap) {
+		self.0.map_entities(map);
+	}
+}
+
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize, serde::Deserialize)]
+struct Name(String);
+
+fn register_scene_types(ecs: &mut ECS) {
+	ecs.registry_mut().register::<Name>().with_serde::<Name>();
+	ecs.registry_mut().register::<Parent>().with_serde::<Parent>().with_map_entities::<Parent>();
+}
+
+#[test]
+fn scene_round_trip() {
+	let (mut ecs, entities) = basic_ecs();
+	register_scene_types(&mut ecs);
+	let root = create_entity_from!(ecs, [Name("root".into())]);
+	let child = create_entity_from!(ecs, [Name("child".into()), Parent(root), A(3)]);
+	ecs.destroy_entity(entities[0]);
+	assert_eq!(ecs.entity_count(), 5);
+
+	let json = ecs.save_scene().unwrap().to_json().unwrap();
+	let scene = Scene::from_json(&json).unwrap();
+	assert_eq!(scene.entity_count(), 5);
+
+	let mut loaded = ECS::new();
+	register_scene_types(&mut loaded);
+	let map = loaded.load_scene(&scene).unwrap();
+	assert_eq!(loaded.entity_count(), 5);
+
+	let (new_root, new_child) = (map.get(&root).unwrap(), map.get(&child).unwrap());
+	assert!(new_root != root);
+	assert_eq!(loaded.get_component::<Parent>(new_child), Some(&Parent(new_root)));
+	assert_eq!(loaded.get_component::<Name>(new_root), Some(&Name("root".into())));
+	assert!(!loaded.has_component::<A>(new_child));
+	assert!(!loaded.contains_entity(entities[1]));
+}
+
+#[test]
+fn scene_unknown_component() {
+	let mut ecs = ECS::new();
+	register_scene_types(&mut ecs);
+	create_entity_from!(ecs, [Name("lonely".into())]);
+	let scene = ecs.save_scene().unwrap();
+
+	let mut loaded = ECS::new();
+	assert!(matches!(loaded.load_scene(&scene), Err(SceneError::UnknownComponent(_))));
+	assert_eq!(loaded.entity_count(), 0);
+
+	register_scene_types(&mut loaded);
+	let mut doubled = scene.clone();
+	let entity = doubled.archetypes[0].entities[0].clone();
+	doubled.archetypes[0].entities.push(entity);
+	assert!(matches!(loaded.load_scene(&doubled), Err(SceneError::DuplicateEntity(_))));
+	let mut doubled = scene.clone();
+	let column = doubled.archetypes[0].components[0];
+	doubled.archetypes[0].components.push(column);
+	assert!(matches!(loaded.load_scene(&doubled), Err(SceneError::DuplicateComponent(_))));
+	assert_eq!(loaded.entity_count(), 0);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+struct Pos { x: f32, y: f32 }
+unsafe impl Pod for Pos {}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Frozen;
+unsafe impl Pod for Frozen {}
+
+impl BinaryCodec for Name {
+	fn encode(&self, out: &mut Vec<u8>) {
+		self.0.encode(out);
+	}
+	fn decode(input: &mut BinaryReader) -> Result<Self, BinaryError> {
+		Ok(Name(String::decode(input)?))
+	}
+}
+
+fn register_binary_types(ecs: &mut ECS) {
+	ecs.registry_mut().register::<Pos>().with_pod::<Pos>();
+	ecs.registry_mut().register::<Frozen>().with_pod::<Frozen>();
+	ecs.registry_mut().register::<Name>().with_codec::<Name>();
+}
+
+#[test]
+fn binary_round_trip() {
+	let mut ecs = ECS::new();
+	register_binary_types(&mut ecs);
+	let mut spawned = Vec::new();
+	for i in 0..100 {
+		let e = create_entity_from!(ecs, [Pos { x: i as f32, y: -(i as f32) }, A(i)]);
+		if i % 3 == 0 {
+			ecs.attach_component(e, Frozen);
+			ecs.attach_component(e, Name(format!("e{}", i)));
+		}
+		spawned.push(e);
+	}
+	let bytes = ecs.save_binary();
+
+	let mut loaded = ECS::new();
+	register_binary_types(&mut loaded);
+	assert_eq!(loaded.load_binary(&bytes).unwrap(), 100);
+	for (i, e) in spawned.iter().enumerate() {
+		assert_eq!(loaded.get_component::<Pos>(*e), Some(&Pos { x: i as f32, y: -(i as f32) }));
+		assert_eq!(loaded.has_component::<Frozen>(*e), i % 3 == 0);
+		assert_eq!(loaded.get_component::<Name>(*e).cloned(), (i % 3 == 0).then(|| Name(format!("e{}", i))));
+		assert!(!loaded.has_component::<A>(*e));
+	}
+	assert_eq!(iter_components!(loaded, EntityId, Pos).count(), 100);
+
+	assert!(matches!(loaded.load_binary(&bytes), Err(BinaryError::DuplicateEntity(_))));
+	assert_eq!(loaded.entity_count(), 100);
+
+	let mut truncated = ECS::new();
+	register_binary_types(&mut truncated);
+	assert!(matches!(truncated.load_binary(&bytes[..bytes.len() - 1]), Err(BinaryError::UnexpectedEof)));
+	assert_eq!(truncated.entity_count(), 0);
+
+	// an archetype claiming more rows than could ever fit
+	let mut huge = bytes[..8].to_vec();
+	huge.extend_from_slice(&0u32.to_le_bytes());
+	huge.extend_from_slice(&1u32.to_le_bytes());
+	huge.extend_from_slice(&0u32.to_le_bytes());
+	huge.extend_from_slice(&u64::MAX.to_le_bytes());
+	assert!(matches!(truncated.load_binary(&huge), Err(BinaryError::Codec(_))));
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Position { x: f32, y: f32 }
+unsafe impl Pod for Position {}
+
+#[test]
+fn stable_ids() {
+	let mut ecs = ECS::new();
+	ecs.registry_mut().register_stable::<Pos>(stable_name!(Pos)).with_pod::<Pos>();
+	let e = create_entity_from!(ecs, [Pos { x: 1., y: 2. }]);
+	assert_eq!(ecs.registry().get(&TypeId::of::<Pos>()).unwrap().stable_id(), StableId::from_name("aecs::tests::Pos"));
+	let bytes = ecs.save_binary();
+
+	// a different Rust type standing in for the same component in another build
+	let mut other = ECS::new();
+	other.registry_mut().register_stable::<Position>("aecs::tests::Pos").with_pod::<Position>();
+	assert_eq!(other.load_binary(&bytes).unwrap(), 1);
+	assert_eq!(other.get_component::<Position>(e), Some(&Position { x: 1., y: 2. }));
+	assert!(other.registry().get_by_stable(StableId::from_name(std::any::type_name::<Position>())).is_none());
+
+	let mut numeric = ECS::new();
+	numeric.registry_mut().register_stable::<Position>(7u64).with_pod::<Position>();
+	assert!(matches!(numeric.load_binary(&bytes), Err(BinaryError::UnknownComponent(id)) if id == StableId::from_name(stable_name!(Pos))));
+}
+
+#[test]
+fn binary_older_formats() {
+	let mut ecs = ECS::new();
+	ecs.registry_mut().register::<Pos>().with_pod::<Pos>();
+	let e = create_entity_from!(ecs, [Pos { x: 1., y: 2. }]);
+	let bytes = ecs.save_binary();
+	// one type entry: stable id, schema version, size, align, pod flag
+	let (header, entry, rest) = (&bytes[..12], &bytes[12..33], &bytes[33..]);
+	let v2 = [&header[..4], &2u32.to_le_bytes(), &header[8..], &entry[..8], &entry[12..], rest].concat();
+	let name = ecs.registry().get(&TypeId::of::<Pos>()).unwrap().name().to_string();
+	let v1 = [&header[..4], &1u32.to_le_bytes(), &header[8..], &(name.len() as u64).to_le_bytes(), name.as_bytes(), &entry[12..], rest].concat();
+
+	for old in [v2, v1] {
+		let mut loaded = ECS::new();
+		loaded.registry_mut().register::<Pos>().with_pod::<Pos>();
+		assert_eq!(loaded.load_binary(&old).unwrap(), 1);
+		assert_eq!(loaded.get_component::<Pos>(e), Some(&Pos { x: 1., y: 2. }));
+	}
+	let future = [&header[..4], &4u32.to_le_bytes(), &bytes[8..]].concat();
+	assert!(matches!(ECS::new().load_binary(&future), Err(BinaryError::UnsupportedVersion(4))));
+}
+
+#[test]
+fn binary_duplicate_columns() {
+	let mut ecs = ECS::new();
+	ecs.registry_mut().register::<Pos>().with_pod::<Pos>();
+	create_entity_from!(ecs, [Pos { x: 1., y: 2. }]);
+	let bytes = ecs.save_binary();
+	let pos = ecs.registry().get(&TypeId::of::<Pos>()).unwrap().stable_id();
+	// after the type table: archetype count, column count, then the column's type index
+	let (head, rest) = (&bytes[..37], &bytes[45..]);
+	let twice = [head, &2u32.to_le_bytes(), &0u32.to_le_bytes(), &0u32.to_le_bytes(), rest].concat();
+	assert!(matches!(ecs.load_binary(&twice), Err(BinaryError::DuplicateComponent(id)) if id == pos));
+
+	// the entity id column can't be smuggled in as a data column either
+	let mut loaded = ECS::new();
+	loaded.registry_mut().register::<EntityId>().with_pod::<EntityId>();
+	let id = loaded.registry().get(&TypeId::of::<EntityId>()).unwrap().stable_id();
+	let as_id = [&bytes[..12], &id.get().to_le_bytes(), &bytes[20..]].concat();
+	assert!(matches!(loaded.load_binary(&as_id), Err(BinaryError::DuplicateComponent(found)) if found == id));
+	assert_eq!(loaded.entity_count(), 0);
+}
+
+#[test]
+#[should_panic(expected = "already used")]
+fn stable_id_collision() {
+	let mut ecs = ECS::new();
+	ecs.registry_mut().register_stable::<Pos>(1u64);
+	ecs.registry_mut().register_stable::<Position>(1u64);
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct HealthV0 { hp: u32 }
+
+#[derive(Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+struct Vitals { health: u32, max: u32 }
+
+#[allow(dead_code)]
+#[derive(Clone, Copy)]
+struct PosV0 { x: f32 }
+unsafe impl Pod for PosV0 {}
+
+#[test]
+fn migrations() {
+	let mut old = ECS::new();
+	old.registry_mut().register_stable::<HealthV0>("vitals").with_serde::<HealthV0>();
+	old.registry_mut().register_stable::<PosV0>("pos").with_pod::<PosV0>();
+	let e = create_entity_from!(old, [HealthV0 { hp: 40 }]);
+	let p = create_entity_from!(old, [PosV0 { x: 3. }]);
+	let scene = Scene::from_json(&old.save_scene().unwrap().to_json().unwrap()).unwrap();
+	let bytes = old.save_binary();
+
+	let mut new = ECS::new();
+	new.registry_mut().register_stable::<Vitals>("vitals").with_serde::<Vitals>().with_version(2)
+		.with_json_migration(0, |mut v| {
+			let hp = v.as_object_mut().and_then(|o| o.remove("hp")).ok_or("missing hp")?;
+			Ok(serde_json::json!({ "health": hp }))
+		});
+	new.registry_mut().register_stable::<Pos>("pos").with_pod::<Pos>().with_version(1)
+		.with_binary_migration(0, |bytes, _| Ok(bytes.chunks(4).flat_map(|x| [x, &[0; 4]].concat()).collect()));
+	assert!(matches!(new.load_scene(&scene), Err(SceneError::Migration(MigrationError::Missing { from: 1, to: 2, .. }))));
+	assert_eq!(new.entity_count(), 0);
+
+	new.registry_mut().register::<Vitals>().with_json_migration(1, |mut v| {
+		let health = v["health"].clone();
+		v["max"] = health;
+		Ok(v)
+	});
+	let map = new.load_scene(&scene).unwrap();
+	assert_eq!(new.get_component::<Vitals>(map.get(&e).unwrap()), Some(&Vitals { health: 40, max: 40 }));
+
+	let mut restored = ECS::new();
+	restored.registry_mut().register_stable::<Pos>("pos").with_pod::<Pos>().with_version(1)
+		.with_binary_migration(0, |bytes, _| Ok(bytes.chunks(4).flat_map(|x| [x, &[0; 4]].concat()).collect()));
+	restored.load_binary(&bytes).unwrap();
+	assert_eq!(restored.get_component::<Pos>(p), Some(&Pos { x: 3., y: 0. }));
+}
+
+fn register_rollback_types(ecs: &mut ECS) {
+	ecs.registry_mut().register::<Pos>().with_rollback_copy::<Pos>();
+	ecs.registry_mut().register::<Name>().with_rollback::<Name>();
+}
+
+#[test]
+fn rollback_restore() {
+	let mut ecs = ECS::new();
+	register_rollback_types(&mut ecs);
+	let e1 = create_entity_from!(ecs, [Pos { x: 1., y: 1. }, Name("one".into()), A(1)]);
+	let e2 = create_entity_from!(ecs, [Pos { x: 2., y: 2. }]);
+	let e4 = create_entity_from!(ecs, [A(4)]);
+	let snapshot = ecs.snapshot();
+	assert_eq!(snapshot.entity_count(), 3);
+
+	// value-only changes take the whole-column path
+	for (_, p) in iter_components_mut!(ecs, EntityId, Pos) {
+		p.x += 10.;
+	}
+	ecs.restore(&snapshot);
+	assert_eq!(ecs.get_component::<Pos>(e1), Some(&Pos { x: 1., y: 1. }));
+
+	ecs.get_component_mut::<Name>(e1).unwrap().0.push('!');
+	ecs.detach_component::<Name>(e1);
+	ecs.destroy_entity(e2);
+	let e3 = create_entity_from!(ecs, [Pos { x: 3., y: 3. }]);
+	ecs.attach_component(e4, Pos { x: 4., y: 4. });
+	ecs.restore(&snapshot);
+
+	assert_eq!(ecs.get_component::<Name>(e1), Some(&Name("one".into())));
+	assert_eq!(ecs.get_component::<A>(e1), Some(&A(1)));
+	assert_eq!(ecs.get_component::<Pos>(e2), Some(&Pos { x: 2., y: 2. }));
+	assert!(!ecs.contains_entity(e3));
+	assert!(!ecs.has_component::<Pos>(e4));
+	assert_eq!(ecs.get_component::<A>(e4), Some(&A(4)));
+	assert_eq!(ecs.entity_count(), 3);
+
+	// an entity without rollback components is still alive after a restore
+	ecs.destroy_entity(e4);
+	ecs.restore(&snapshot);
+	assert!(ecs.contains_entity(e4));
+	assert!(ecs.diff_snapshot(&snapshot).is_empty());
+
+	// everything spawned since goes away, and a seeded world hands the same ids out again
+	let mut ecs = ECS::with_seed(9);
+	register_rollback_types(&mut ecs);
+	let snapshot = ecs.snapshot();
+	let plain = create_entity_from!(ecs, [A(5)]);
+	ecs.restore(&snapshot);
+	assert!(!ecs.contains_entity(plain));
+	assert_eq!(ecs.create_entity(), plain);
+}
+
+#[test]
+fn rollback_resimulate() {
+	let mut ecs = ECS::new();
+	register_rollback_types(&mut ecs);
+	let e = create_entity_from!(ecs, [Pos { x: 0., y: 0. }]);
+	let mut buffer = RollbackBuffer::new(4);
+	let step = |ecs: &mut ECS, _frame: u64| ecs.get_component_mut::<Pos>(e).unwrap().x += 1.;
+
+	for frame in 0..6 {
+		buffer.save(frame, &ecs);
+		step(&mut ecs, frame);
+	}
+	assert_eq!((buffer.oldest_frame(), buffer.latest_frame()), (Some(2), Some(5)));
+	assert!(!buffer.resimulate(&mut ecs, 1, 6, step));
+
+	// late input arrives for frame 3: it should have moved y as well
+	assert!(buffer.resimulate(&mut ecs, 3, 6, |ecs, frame| {
+		let p = ecs.get_component_mut::<Pos>(e).unwrap();
+		p.x += 1.;
+		if frame == 3 { p.y = 1.; }
+	}));
+	assert_eq!(ecs.get_component::<Pos>(e), Some(&Pos { x: 6., y: 1. }));
+	assert_eq!(buffer.latest_frame(), Some(6));
+
+	assert!(buffer.rollback(&mut ecs, 4));
+	assert_eq!(ecs.get_component::<Pos>(e), Some(&Pos { x: 4., y: 1. }));
+}
+
+impl StableHash for Pos {
+	fn stable_hash(&self, state: &mut StableHasher) {
+		self.x.stable_hash(state);
+		self.y.stable_hash(state);
+	}
+}
+impl StableHash for Name {
+	fn stable_hash(&self, state: &mut StableHasher) {
+		self.0.stable_hash(state);
+	}
+}
+
+fn lockstep_world() -> (ECS, Vec<EntityId>) {
+	let mut ecs = ECS::new();
+	ecs.registry_mut().register_stable::<Pos>("pos").with_stable_hash::<Pos>();
+	ecs.registry_mut().register_stable::<Name>("name").with_stable_hash::<Name>();
+	let mut entities = Vec::new();
+	for i in 0..20 {
+		let e = create_entity_from!(ecs, [Pos { x: i as f32, y: 0. }]);
+		match i % 3 {
+			0 => ecs.attach_component(e, Name(format!("n{}", i))),
+			1 => ecs.attach_component(e, A(i)),
+			_ => {},
+		}
+		entities.push(e);
+	}
+	ecs.destroy_entity(entities[4]);
+	(ecs, entities)
+}
+
+#[test]
+fn checksums() {
+	let (a, _) = lockstep_world();
+	let (mut b, entities) = lockstep_world();
+	assert_eq!(a.checksum_by_component(), b.checksum_by_component());
+
+	b.get_component_mut::<Pos>(entities[7]).unwrap().y = 0.5;
+	let (sum_a, sum_b) = (a.checksum_by_component(), b.checksum_by_component());
+	assert!(sum_a.total != sum_b.total);
+	assert_eq!(sum_a.diverging_components(&sum_b), vec![StableId::from_name("pos")]);
+
+	// unhashed components still count structurally
+	let (mut c, entities) = lockstep_world();
+	c.attach_component(entities[2], B(1.));
+	assert!(a.checksum() != c.checksum());
+}
+
+#[test]
+fn deterministic_mode() {
+	let build = |ecs: &mut ECS| {
+		for i in 0..50 {
+			let e = create_entity_from!(ecs, [A(i)]);
+			if i % 2 == 0 { ecs.attach_component(e, B(i as f32)); }
+			if i % 5 == 0 { ecs.attach_component(e, C(i as i64)); }
+			if i % 7 == 0 { ecs.detach_component::<A>(e); }
+		}
+	};
+	let (mut first, mut second) = (ECS::with_seed(42), ECS::with_seed(42));
+	build(&mut first);
+	build(&mut second);
+	assert!(first.is_deterministic() && !ECS::new().is_deterministic());
+
+	let order = |ecs: &ECS| iter_components!(ecs, EntityId, A).map(|(e, a)| (*e, a.0)).collect::<Vec<_>>();
+	assert_eq!(order(&first), order(&second));
+	assert_eq!(first.entities().collect::<Vec<_>>(), second.entities().collect::<Vec<_>>());
+	assert_eq!(
+		first.archetypes().iter().map(|(id, a)| (id, a.len())).collect::<Vec<_>>(),
+		second.archetypes().iter().map(|(id, a)| (id, a.len())).collect::<Vec<_>>(),
+	);
+
+	let mut other_seed = ECS::with_seed(7);
+	build(&mut other_seed);
+	assert!(first.entities().next() != other_seed.entities().next());
+}
+
+#[test]
+fn world_rng() {
+	use rand::Rng;
+	let (mut a, mut b) = (ECS::with_seed(9), ECS::with_seed(9));
+	let e = a.create_entity();
+	assert_eq!(e, b.create_entity());
+
+	let rolls: Vec<u32> = (0..8).map(|_| a.rng_mut().gen_range(0..100)).collect();
+	let ai = a.rng_mut().system_stream("ai").gen::<u64>();
+	// draw from b's streams in a different order, they must not affect each other
+	let loot = b.rng_mut().entity_stream(e).gen::<u64>();
+	assert_eq!(b.rng_mut().system_stream("ai").gen::<u64>(), ai);
+	assert_eq!((0..8).map(|_| b.rng_mut().gen_range(0..100)).collect::<Vec<u32>>(), rolls);
+	assert_eq!(a.rng_mut().entity_stream(e).gen::<u64>(), loot);
+	assert_eq!(a.rng(), b.rng());
+
+	let json = serde_json::to_string(a.rng()).unwrap();
+	let mut copy: WorldRng = serde_json::from_str(&json).unwrap();
+	assert_eq!(copy.gen::<u64>(), a.rng_mut().gen::<u64>());
+
+	let snapshot = a.snapshot();
+	let next = a.rng_mut().gen::<u64>();
+	a.restore(&snapshot);
+	assert_eq!(a.rng_mut().gen::<u64>(), next);
+}
+
+fn sync(server: &mut ECS, replicator: &mut Replicator, client: &mut ECS, replica: &mut Replica) -> Delta {
+	let bytes = replicator.collect(server).unwrap().to_bytes().unwrap();
+	server.advance_tick();
+	let delta = Delta::from_bytes(&bytes).unwrap();
+	replica.apply(client, &delta).unwrap();
+	delta
+}
+
+#[test]
+fn replication() {
+	let (mut server, mut client) = (ECS::new(), ECS::new());
+	for ecs in [&mut server, &mut client] {
+		ecs.registry_mut().register::<Name>().with_replication::<Name>();
+		ecs.registry_mut().register::<Parent>().with_replication::<Parent>().with_map_entities::<Parent>();
+	}
+	let (mut replicator, mut replica) = (Replicator::new(), Replica::new());
+
+	let root = create_entity_from!(server, [Name("root".into())]);
+	let child = create_entity_from!(server, [Name("child".into()), Parent(root), A(1)]);
+	let _hidden = create_entity_from!(server, [A(2)]);
+	let delta = sync(&mut server, &mut replicator, &mut client, &mut replica);
+	assert_eq!((delta.spawned.len(), delta.inserted.len()), (2, 3));
+	assert_eq!(client.entity_count(), 2);
+	let (l_root, l_child) = (replica.local(root).unwrap(), replica.local(child).unwrap());
+	assert_eq!(client.get_component::<Parent>(l_child), Some(&Parent(l_root)));
+	assert!(!client.has_component::<A>(l_child));
+	// rows from the last collected tick go out once more, they may have been written after the collect
+	assert_eq!(sync(&mut server, &mut replicator, &mut client, &mut replica).changed.len(), 3);
+	assert!(sync(&mut server, &mut replicator, &mut client, &mut replica).is_empty());
+
+	server.get_component_mut::<Name>(root).unwrap().0 = "renamed".into();
+	server.detach_component::<Parent>(child);
+	server.attach_component(child, A(3));
+	let added = create_entity_from!(server, [Name("added".into())]);
+	let delta = sync(&mut server, &mut replicator, &mut client, &mut replica);
+	assert_eq!((delta.spawned.len(), delta.changed.len(), delta.removed.len()), (1, 1, 1));
+	assert_eq!(client.get_component::<Name>(l_root), Some(&Name("renamed".into())));
+	assert!(!client.has_component::<Parent>(l_child));
+	assert_eq!(client.get_component::<Name>(replica.local(added).unwrap()), Some(&Name("added".into())));
+
+	for (_, name) in iter_components_mut!(server, EntityId, Name) {
+		name.0.push('!');
+	}
+	server.destroy_entity(root);
+	let delta = sync(&mut server, &mut replicator, &mut client, &mut replica);
+	assert_eq!((delta.changed.len(), delta.despawned.len()), (2, 1));
+	assert!(!client.contains_entity(l_root) && replica.local(root).is_none());
+	assert_eq!(client.get_component::<Name>(l_child), Some(&Name("child!".into())));
+
+	let bad = Delta { changed: vec![(root, StableId::new(1), serde_json::Value::Null)], ..Default::default() };
+	assert!(matches!(replica.apply(&mut client, &bad), Err(ReplicationError::UnknownComponent(_))));
+
+	// a write after the collect, still in the same tick, reaches the client with the next frames
+	let delta = replicator.collect(&server).unwrap();
+	replica.apply(&mut client, &delta).unwrap();
+	server.get_component_mut::<Name>(child).unwrap().0 = "late".into();
+	server.advance_tick();
+	sync(&mut server, &mut replicator, &mut client, &mut replica);
+	sync(&mut server, &mut replicator, &mut client, &mut replica);
+	assert_eq!(client.get_component::<Name>(l_child), Some(&Name("late".into())));
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+struct Team(u8);
+
+#[test]
+fn relevance() {
+	let mut server = ECS::new();
+	server.registry_mut().register::<Name>().with_replication::<Name>();
+	server.registry_mut().register::<Team>().with_replication::<Team>();
+	server.registry_mut().register::<Pos>().with_replication::<Pos>();
+	let red = create_entity_from!(server, [Name("red".into()), Team(0), Pos { x: 0., y: 0. }]);
+	let blue = create_entity_from!(server, [Name("blue".into()), Team(1), Pos { x: 50., y: 0. }]);
+
+	let mut team = Replicator::with_relevance(SameTeam(Team(0)));
+	let delta = team.collect(&server).unwrap();
+	assert_eq!(delta.spawned, vec![red]);
+	assert_eq!(team.drain_events().collect::<Vec<_>>(), vec![RelevanceEvent::Enter(red)]);
+
+	let mut near = Replicator::with_relevance(Distance::new([0., 0., 0.], 10., |p: &Pos| [p.x, p.y, 0.]));
+	assert_eq!(near.collect(&server).unwrap().spawned, vec![red]);
+	near.drain_events().for_each(drop);
+	server.advance_tick();
+	server.get_component_mut::<Pos>(blue).unwrap().x = 5.;
+	server.get_component_mut::<Pos>(red).unwrap().x = 20.;
+	let delta = near.collect(&server).unwrap();
+	assert_eq!((delta.spawned, delta.despawned), (vec![blue], vec![red]));
+	assert_eq!(near.drain_events().collect::<Vec<_>>(), vec![RelevanceEvent::Enter(blue), RelevanceEvent::Leave(red)]);
+
+	let mut visible = VisibilitySet::new();
+	visible.insert(blue);
+	visible.hide_component(server.registry().get(&TypeId::of::<Pos>()).unwrap().stable_id());
+	let mut client = Replicator::with_relevance(visible);
+	let delta = client.collect(&server).unwrap();
+	assert_eq!((delta.spawned.len(), delta.inserted.len()), (1, 2));
+	client.relevance_mut().remove(blue);
+	server.destroy_entity(blue);
+	assert_eq!(client.collect(&server).unwrap().despawned, vec![blue]);
+	// a despawn is not a relevance change
+	assert_eq!(client.drain_events().count(), 1);
+
+	let mut either = Replicator::with_relevance(AnyOf(vec![Box::new(SameTeam(Team(0))), Box::new(move |_: &ECS, e: EntityId| e == blue)]));
+	assert_eq!(either.collect(&server).unwrap().spawned, vec![red]);
+}
+
+#[test]
+fn journal_replay() {
+	let mut ecs = ECS::new();
+	register_scene_types(&mut ecs);
+	ecs.start_journal();
+	let root = create_entity_from!(ecs, [Name("root".into())]);
+	let child = create_entity_from!(ecs, [Name("child".into()), Parent(root), A(1)]);
+	let gone = create_entity_from!(ecs, [Name("gone".into())]);
+	ecs.get_component_mut::<Name>(root).unwrap().0 = "renamed".into();
+	ecs.record_changes();
+	for (_, name) in iter_components_mut!(ecs, EntityId, Name) {
+		name.0.push('!');
+	}
+	ecs.detach_component::<Parent>(child);
+	ecs.destroy_entity(gone);
+	assert!(matches!(ecs.load_scene(&Scene::default()), Err(SceneError::Journaling)));
+	let journal = ecs.stop_journal().unwrap();
+	assert!(!ecs.is_journaling());
+	// attaches don't come back as sets, and swept writes land ahead of the detach that followed them
+	let sets: Vec<usize> = journal.ops.iter().enumerate().filter(|(_, op)| matches!(op, JournalOp::Set { .. })).map(|(i, _)| i).collect();
+	assert_eq!(sets.len(), 4);
+	assert!(matches!(journal.ops[sets[3] + 1], JournalOp::Detach { .. }));
+
+	let path = std::env::temp_dir().join(format!("aecs-journal-{:?}.json", root));
+	journal.save(&path).unwrap();
+	let loaded = Journal::load(&path).unwrap();
+	std::fs::remove_file(&path).unwrap();
+	assert_eq!(loaded.ops, journal.ops);
+
+	let mut copy = ECS::new();
+	register_scene_types(&mut copy);
+	loaded.replay(&mut copy).unwrap();
+	assert_eq!(copy.entities().collect::<HashSet<_>>(), ecs.entities().collect::<HashSet<_>>());
+	assert_eq!(copy.get_component::<Name>(root), Some(&Name("renamed!".into())));
+	assert!(!copy.has_component::<Parent>(child) && !copy.has_component::<A>(child));
+
+	// stepping stops right after the child is created
+	let mut stepped = ECS::new();
+	register_scene_types(&mut stepped);
+	let mut replay = loaded.replayer();
+	let at = loaded.ops.iter().position(|op| *op == JournalOp::Create { entity: child }).unwrap();
+	replay.run_to(&mut stepped, at + 1).unwrap();
+	assert!(stepped.contains_entity(child) && !stepped.has_component::<Name>(child));
+	assert!(matches!(replay.step(&mut stepped).unwrap(), Some(JournalOp::Attach { .. })));
+	assert_eq!(stepped.get_component::<Name>(child), Some(&Name("child".into())));
+	let destroy = loaded.ops.iter().position(|op| *op == JournalOp::Destroy { entity: gone }).unwrap();
+	replay.run_to(&mut stepped, destroy).unwrap();
+	assert_eq!(stepped.get_component::<Name>(gone), Some(&Name("gone!".into())));
+	assert!(matches!(loaded.replay(&mut stepped), Err(JournalError::DuplicateEntity(_))));
+
+	// a seeded world replaying its own journal doesn't hand the replayed ids out again
+	let mut seeded = ECS::with_seed(7);
+	register_scene_types(&mut seeded);
+	seeded.start_journal();
+	let first = seeded.create_entity();
+	let journal = seeded.stop_journal().unwrap();
+	let mut replayed = ECS::with_seed(7);
+	register_scene_types(&mut replayed);
+	journal.replay(&mut replayed).unwrap();
+	assert_ne!(replayed.create_entity(), first);
+	assert_eq!(replayed.entity_count(), 2);
+}
+
+#[test]
+fn undo_redo() {
+	let mut ecs = ECS::new();
+	let kept = create_entity_from!(ecs, [A(1), Name("kept".into())]);
+
+	ecs.begin_undo_group();
+	let spawned = create_entity_from!(ecs, [A(2), B(1.5)]);
+	ecs.attach_component(kept, A(10));
+	ecs.detach_component::<Name>(kept);
+	ecs.edit_component::<A>(kept, |a| a.0 += 1);
+	ecs.end_undo_group();
+	assert_eq!(ecs.get_component::<A>(kept), Some(&A(11)));
+
+	ecs.begin_undo_group();
+	ecs.destroy_entity(kept);
+	ecs.end_undo_group();
+	assert!(!ecs.contains_entity(kept));
+
+	assert!(ecs.undo());
+	assert_eq!(ecs.component_types(kept), vec_of_types(&[TypeId::of::<EntityId>(), TypeId::of::<A>()]));
+	assert_eq!(ecs.get_component::<A>(kept), Some(&A(11)));
+
+	assert!(ecs.undo() && !ecs.can_undo());
+	assert!(!ecs.contains_entity(spawned));
+	assert_eq!(ecs.get_component::<A>(kept), Some(&A(1)));
+	assert_eq!(ecs.get_component::<Name>(kept), Some(&Name("kept".into())));
+
+	assert!(ecs.redo());
+	assert_eq!(ecs.get_component::<B>(spawned), Some(&B(1.5)));
+	assert_eq!(ecs.get_component::<A>(kept), Some(&A(11)));
+	assert!(!ecs.has_component::<Name>(kept));
+
+	// new edits drop the redo branch
+	ecs.begin_undo_group();
+	ecs.attach_component(spawned, C(3));
+	ecs.end_undo_group();
+	assert!(!ecs.can_redo() && ecs.undo());
+	assert!(!ecs.has_component::<C>(spawned));
+
+	// undo and redo reach the journal as the edits they make
+	let mut ecs = ECS::new();
+	register_scene_types(&mut ecs);
+	ecs.start_journal();
+	let kept = ecs.spawn((Name("kept".into()),));
+	ecs.begin_undo_group();
+	ecs.attach_component(kept, Name("renamed".into()));
+	let added = ecs.spawn((Name("added".into()),));
+	ecs.end_undo_group();
+	ecs.undo();
+	let mut copy = ECS::new();
+	register_scene_types(&mut copy);
+	ecs.journal().unwrap().replay(&mut copy).unwrap();
+	assert!(!copy.contains_entity(added));
+	assert_eq!(copy.get_component::<Name>(kept), Some(&Name("kept".into())));
+	ecs.redo();
+	let mut copy = ECS::new();
+	register_scene_types(&mut copy);
+	ecs.stop_journal().unwrap().replay(&mut copy).unwrap();
+	assert_eq!(copy.get_component::<Name>(added), Some(&Name("added".into())));
+	assert_eq!(copy.get_component::<Name>(kept), Some(&Name("renamed".into())));
+}
+
+fn vec_of_types(types: &[TypeId]) -> Vec<TypeId> {
+	let mut types = types.to_vec();
+	types.sort();
+	types
+}
+
+#[test]
+fn transactions() {
+	let mut ecs = ECS::with_seed(3);
+	let from = create_entity_from!(ecs, [Health(10)]);
+	let to = create_entity_from!(ecs, [Health(0), A(1)]);
+	let transfer = |ecs: &mut ECS, amount: u32| ecs.transaction(|tx| {
+		let receipt = tx.spawn();
+		tx.attach(receipt, Name(format!("sent {}", amount)));
+		let have = tx.get::<Health>(from).ok_or("missing")?.0;
+		if have < amount {
+			return Err("not enough");
+		}
+		tx.attach(from, Health(have - amount));
+		tx.attach(to, Health(tx.get::<Health>(to).unwrap().0 + amount));
+		tx.detach::<A>(to);
+		Ok(receipt)
+	});
+
+	let layout = |ecs: &ECS| ecs.archetypes().iter().map(|(id, a)| (id, a.len())).collect::<Vec<_>>();
+	let before = layout(&ecs);
+	assert_eq!(transfer(&mut ecs, 50), Err("not enough"));
+	assert_eq!(layout(&ecs), before);
+	assert_eq!((ecs.entity_count(), ecs.get_component::<Health>(from)), (2, Some(&Health(10))));
+
+	let receipt = transfer(&mut ecs, 4).unwrap();
+	assert_eq!(ecs.get_component::<Health>(from), Some(&Health(6)));
+	assert_eq!(ecs.get_component::<Health>(to), Some(&Health(4)));
+	assert!(!ecs.has_component::<A>(to));
+	assert_eq!(ecs.get_component::<Name>(receipt), Some(&Name("sent 4".into())));
+	// the failed attempt gave its id back
+	let mut replay = ECS::with_seed(3);
+	replay.create_entity();
+	replay.create_entity();
+	assert_eq!(replay.create_entity(), receipt);
+
+	// staged writes are visible inside, a failed attempt registers nothing and a dead target drops its value
+	let counter = std::rc::Rc::new(());
+	let result: Result<(), ()> = ecs.transaction(|tx| {
+		tx.attach(to, Health(9));
+		assert_eq!(tx.get::<Health>(to), Some(&Health(9)));
+		tx.detach::<Health>(to);
+		assert_eq!(tx.get::<Health>(to), None);
+		tx.attach(to, counter.clone());
+		Err(())
+	});
+	assert!(result.is_err() && ecs.registry().get(&TypeId::of::<std::rc::Rc<()>>()).is_none());
+	ecs.transaction(|tx| {
+		tx.despawn(receipt);
+		tx.attach(receipt, counter.clone());
+		Ok::<_, ()>(())
+	}).unwrap();
+	assert_eq!(std::rc::Rc::strong_count(&counter), 1);
+}
+
+#[test]
+fn world_diff() {
+	let build = || {
+		let mut ecs = ECS::with_seed(11);
+		ecs.registry_mut().register::<A>().with_eq::<A>().with_debug::<A>();
+		ecs.registry_mut().register::<B>().with_eq::<B>();
+		let e = [create_entity_from!(ecs, [A(1), B(1.)]), create_entity_from!(ecs, [A(2)]), create_entity_from!(ecs, [C(3)])];
+		(ecs, e)
+	};
+	let (mut left, e) = build();
+	let (mut right, _) = build();
+	assert!(left.diff(&right).is_empty());
+
+	left.attach_component(e[0], A(5));
+	left.attach_component(e[0], B(2.));
+	right.attach_component(e[1], C(0));
+	right.destroy_entity(e[2]);
+	let extra = right.create_entity();
+	let diff = left.diff(&right);
+	assert_eq!((diff.only_left.clone(), diff.only_right.clone()), (vec![e[2]], vec![extra]));
+	assert_eq!(diff.signatures.len(), 1);
+	assert_eq!(diff.signatures[0].right.len(), 2);
+	assert_eq!(diff.values.len(), 2);
+	assert!(diff.values.iter().any(|v| v.left == "A(5)" && v.right == "A(1)"));
+	assert!(diff.values.iter().any(|v| v.left == "<no debug>"));
+	let report = diff.to_string();
+	assert!(report.starts_with("5 difference(s)") && report.contains("A(5) vs A(1)"));
+
+	let mut ecs = ECS::new();
+	register_rollback_types(&mut ecs);
+	ecs.registry_mut().register::<Pos>().with_eq::<Pos>().with_debug::<Pos>();
+	let mover = create_entity_from!(ecs, [Pos { x: 0., y: 0. }, A(0)]);
+	let snapshot = ecs.snapshot();
+	assert!(ecs.diff_snapshot(&snapshot).is_empty());
+	ecs.get_component_mut::<Pos>(mover).unwrap().x = 1.;
+	ecs.attach_component(mover, A(9));
+	let diff = ecs.diff_snapshot(&snapshot);
+	assert_eq!((diff.len(), diff.values[0].right.as_str()), (1, "Pos { x: 0.0, y: 0.0 }"));
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Selected(u8);
+
+#[test]
+fn sparse_storage() {
+	let (mut ecs, entities) = basic_ecs();
+	ecs.registry_mut().register::<Selected>().with_sparse_storage();
+	let archetypes = ecs.archetypes().len();
+	let layout = |ecs: &ECS| ecs.entities().collect::<Vec<_>>();
+	let before = layout(&ecs);
+
+	for e in entities.iter().take(3) {
+		ecs.attach_component(*e, Selected(1));
+	}
+	ecs.detach_component::<Selected>(entities[1]);
+	ecs.attach_component(entities[0], Selected(2));
+	// no archetype moves, the row order of every table is untouched
+	assert_eq!((ecs.archetypes().len(), layout(&ecs)), (archetypes, before));
+	assert!(ecs.has_component::<Selected>(entities[2]) && !ecs.has_component::<Selected>(entities[1]));
+	assert!(ecs.component_types(entities[0]).contains(&TypeId::of::<Selected>()));
+
+	let mut mixed: Vec<_> = query!(ecs, A, Selected).map(|(e, a, s)| (e, a.0, s.0)).collect();
+	mixed.sort_by_key(|(_, a, _)| *a);
+	assert_eq!(mixed, vec![(entities[0], 10, 2)]);
+	assert_eq!(query!(ecs, Selected).count(), 2);
+	assert_eq!(query!(ecs, A, B).count(), 2);
+	ecs.get_component_mut::<Selected>(entities[2]).unwrap().0 = 7;
+	assert_eq!(ecs.get_component::<Selected>(entities[2]), Some(&Selected(7)));
+
+	ecs.begin_undo_group();
+	ecs.destroy_entity(entities[2]);
+	ecs.end_undo_group();
+	assert_eq!(query!(ecs, Selected).count(), 1);
+	ecs.undo();
+	assert_eq!(ecs.get_component::<Selected>(entities[2]), Some(&Selected(7)));
+	assert_eq!(ecs.get_component::<C>(entities[2]), Some(&C(-4)));
+}
+
+impl StableHash for Selected {
+	fn stable_hash(&self, state: &mut StableHasher) {
+		state.write(&[self.0]);
+	}
+}
+
+#[test]
+fn sparse_rollback_and_checksum() {
+	let (mut ecs, entities) = basic_ecs();
+	ecs.registry_mut().register::<Selected>().with_sparse_storage().with_rollback::<Selected>()
+		.with_stable_hash::<Selected>().with_eq::<Selected>();
+	ecs.attach_component(entities[0], Selected(1));
+	let (snapshot, checksum) = (ecs.snapshot(), ecs.checksum());
+
+	ecs.get_component_mut::<Selected>(entities[0]).unwrap().0 = 5;
+	ecs.attach_component(entities[1], Selected(2));
+	assert_ne!(ecs.checksum(), checksum);
+	assert_eq!(ecs.diff_snapshot(&snapshot).len(), 2);
+	ecs.restore(&snapshot);
+	assert_eq!(ecs.get_component::<Selected>(entities[0]), Some(&Selected(1)));
+	assert!(!ecs.has_component::<Selected>(entities[1]));
+	assert_eq!(ecs.checksum(), checksum);
+	assert!(ecs.diff_snapshot(&snapshot).is_empty());
+
+	// identical worlds agree even though their random entity ids don't
+	let build = || {
+		let mut ecs = ECS::new();
+		ecs.registry_mut().register::<Selected>().with_sparse_storage().with_stable_hash::<Selected>();
+		let e = create_entity_from!(ecs, [A(1)]);
+		create_entity_from!(ecs, [A(2)]);
+		ecs.attach_component(e, Selected(3));
+		ecs.checksum()
+	};
+	assert_eq!(build(), build());
+}
+
+#[test]
+#[should_panic(expected = "can't use sparse storage")]
+fn sparse_after_serde() {
+	let mut ecs = ECS::new();
+	ecs.registry_mut().register::<Name>().with_serde::<Name>().with_sparse_storage();
+}
+
+#[test]
+#[should_panic(expected = "can't be saved or replicated")]
+fn serde_after_sparse() {
+	let mut ecs = ECS::new();
+	ecs.registry_mut().register::<Name>().with_sparse_storage().with_replication::<Name>();
+}
+
+#[test]
+#[should_panic(expected = "already stored in an archetype")]
+fn sparse_after_table() {
+	let (mut ecs, _) = basic_ecs();
+	ecs.registry_mut().register::<A>().with_sparse_storage();
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Mesh { name: &'static str, lod: u32 }
+
+#[test]
+fn shared_components() {
+	let mut ecs = ECS::new();
+	let mut rocks = Vec::new();
+	for i in 0..100 {
+		let e = create_entity_from!(ecs, [A(i)]);
+		let mesh = if i % 10 == 0 { Mesh { name: "tree", lod: 2 } } else { Mesh { name: "rock", lod: 1 } };
+		if mesh.name == "rock" { rocks.push(e); }
+		ecs.attach_shared(e, mesh);
+	}
+	assert_eq!(ecs.shared_count::<Mesh>(), 2);
+	assert_eq!(ecs.get_shared::<Mesh>(rocks[0]).unwrap().name, "rock");
+
+	let groups: Vec<(&str, usize)> = ecs.iter_shared_groups::<Mesh>().map(|(mesh, group)| (mesh.name, group.len())).collect();
+	assert_eq!(groups, vec![("tree", 10), ("rock", 90)]);
+	let (handle_entity, handle) = query!(ecs, A, Shared<Mesh>).map(|(e, _, h)| (e, *h)).next().unwrap();
+	assert_eq!(ecs.shared(handle), ecs.get_shared::<Mesh>(handle_entity));
+
+	let lods: u32 = query!(ecs, A, Shared<&Mesh>).map(|(_, _, mesh)| mesh.lod).sum();
+	assert_eq!(lods, 10 * 2 + 90);
+
+	// once no tree is left the pool drops it, handles to rocks keep pointing at rocks
+	let snapshot = ecs.snapshot();
+	let trees: Vec<EntityId> = ecs.iter_shared_groups::<Mesh>().next().unwrap().1;
+	let tree = *ecs.get_component::<Shared<Mesh>>(trees[0]).unwrap();
+	for e in trees.iter() {
+		ecs.detach_shared::<Mesh>(*e);
+	}
+	ecs.attach_shared(rocks[0], Mesh { name: "boulder", lod: 3 });
+	assert_eq!(ecs.prune_shared::<Mesh>(), 1);
+	assert_eq!(ecs.get_shared::<Mesh>(rocks[1]), Some(&Mesh { name: "rock", lod: 1 }));
+	assert_eq!(ecs.get_shared::<Mesh>(rocks[0]).unwrap().name, "boulder");
+	assert_eq!(ecs.iter_shared_groups::<Mesh>().count(), 2);
+	// the tree's slot is reused, its old handle doesn't see the new value
+	let bush = ecs.attach_shared(trees[0], Mesh { name: "bush", lod: 4 });
+	assert_eq!((bush.index(), ecs.shared(tree)), (tree.index(), None));
+	let rock = *ecs.get_component::<Shared<Mesh>>(rocks[1]).unwrap();
+	assert_eq!(ecs.attach_shared(trees[1], Mesh { name: "rock", lod: 1 }), rock);
+
+	ecs.restore(&snapshot);
+	assert_eq!(ecs.shared(tree).unwrap().name, "tree");
+	assert_eq!(ecs.shared_count::<Mesh>(), 2);
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Hitbox { radius: f32 }
+
+#[test]
+fn multiple_instances() {
+	let (mut ecs, entities) = basic_ecs();
+	let head = ecs.add_instance(entities[0], Hitbox { radius: 1. }).unwrap();
+	let body = ecs.add_instance(entities[0], Hitbox { radius: 3. }).unwrap();
+	ecs.add_instance(entities[3], Hitbox { radius: 2. });
+	assert_eq!(ecs.add_instance(ECS::new().create_entity(), Hitbox { radius: 0. }), None);
+	assert_eq!(ecs.instance_count::<Hitbox>(entities[0]), 2);
+
+	let mut all: Vec<(EntityId, Vec<f32>)> = query!(ecs, A, Multi<Hitbox>)
+		.map(|(e, _, boxes)| (e, boxes.values().map(|h| h.radius).collect()))
+		.collect();
+	all.sort_by_key(|(_, radii)| radii.len());
+	assert_eq!(all, vec![(entities[3], vec![2.]), (entities[0], vec![1., 3.])]);
+
+	ecs.get_component_mut::<Multi<Hitbox>>(entities[0]).unwrap().get_mut(body).unwrap().radius = 4.;
+	assert_eq!(ecs.remove_instance::<Hitbox>(entities[0], head), Some(Hitbox { radius: 1. }));
+	assert_eq!(ecs.remove_instance::<Hitbox>(entities[0], head), None);
+	assert_eq!(ecs.instances::<Hitbox>(entities[0]).collect::<Vec<_>>(), vec![&Hitbox { radius: 4. }]);
+	ecs.remove_instance::<Hitbox>(entities[0], body);
+	assert!(!ecs.has_component::<Multi<Hitbox>>(entities[0]));
+	assert_eq!(ecs.instances::<Hitbox>(entities[0]).count(), 0);
+
+	ecs.begin_undo_group();
+	let arm = ecs.add_instance(entities[3], Hitbox { radius: 5. }).unwrap();
+	ecs.remove_instance::<Hitbox>(entities[3], arm);
+	let first = ecs.get_component::<Multi<Hitbox>>(entities[3]).unwrap().iter().next().unwrap().0;
+	ecs.remove_instance::<Hitbox>(entities[3], first);
+	ecs.end_undo_group();
+	assert!(!ecs.has_component::<Multi<Hitbox>>(entities[3]));
+	assert!(ecs.undo());
+	assert_eq!(ecs.instances::<Hitbox>(entities[3]).collect::<Vec<_>>(), vec![&Hitbox { radius: 2. }]);
+
+	// the persistence traits come with Multi, only Hitbox needs them
+	ecs.registry_mut().register::<Multi<Hitbox>>().with_serde::<Multi<Hitbox>>().with_codec::<Multi<Hitbox>>();
+	let scene = ecs.save_scene().unwrap();
+	let mut loaded = ECS::new();
+	loaded.registry_mut().register::<Multi<Hitbox>>().with_serde::<Multi<Hitbox>>();
+	let map = loaded.load_scene(&scene).unwrap();
+	assert_eq!(loaded.instances::<Hitbox>(map.get(&entities[3]).unwrap()).count(), 1);
+}
+
+impl serde::Serialize for Hitbox {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		self.radius.serialize(serializer)
+	}
+}
+impl<'de> serde::Deserialize<'de> for Hitbox {
+	fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		Ok(Hitbox { radius: f32::deserialize(deserializer)? })
+	}
+}
+impl BinaryCodec for Hitbox {
+	fn encode(&self, out: &mut Vec<u8>) {
+		out.extend_from_slice(&self.radius.to_le_bytes());
+	}
+	fn decode(input: &mut BinaryReader) -> Result<Self, BinaryError> {
+		Ok(Hitbox { radius: f32::from_le_bytes(input.take(4)?.try_into().unwrap()) })
+	}
+}
+
+#[test]
+fn chunked_storage() {
+	let mut ecs = ECS::new();
+	let first = create_entity_from!(ecs, [A(0), B(0.)]);
+	let pinned = ecs.get_component::<A>(first).unwrap() as *const A;
+	let mut entities = vec![first];
+	for i in 1..5000 {
+		entities.push(create_entity_from!(ecs, [A(i), B(i as f32)]));
+	}
+	// growing past many chunks leaves earlier rows where they were
+	assert_eq!(ecs.get_component::<A>(first).unwrap() as *const A, pinned);
+	let a_id = ecs.entity_index[&first].0;
+	let (rows, chunks) = {
+		let arche = ecs.archetypes().get(&a_id).unwrap();
+		(arche.chunk_rows(), arche.chunk_count())
+	};
+	assert!(rows > 1 && chunks >= 5000 / rows);
+
+	let mut seen = 0;
+	for (a, b) in iter_chunks!(ecs, A, B) {
+		assert_eq!(a.len(), b.len());
+		assert!(a.len() <= rows);
+		assert!(a.iter().zip(b).all(|(a, b)| a.0 as f32 == b.0));
+		seen += a.len();
+	}
+	assert_eq!(seen, 5000);
+	for (a, b) in iter_chunks_mut!(ecs, A, B) {
+		a.iter_mut().zip(b.iter_mut()).for_each(|(a, b)| b.0 = -(a.0 as f32));
+	}
+	assert_eq!(ecs.get_component::<B>(entities[4999]), Some(&B(-4999.)));
+
+	// rows written since a tick are found without looking at the chunks nobody touched
+	let tick = ecs.change_tick();
+	ecs.advance_tick();
+	ecs.get_component_mut::<A>(entities[4321]);
+	let (_, row) = ecs.entity_index[&entities[4321]];
+	let arche = ecs.archetypes().get(&a_id).unwrap();
+	assert_eq!(arche.get_column(&TypeId::of::<A>()).unwrap().changed_since(tick).collect::<Vec<_>>(), vec![row]);
+	assert_eq!(arche.get_column(&TypeId::of::<B>()).unwrap().changed_since(tick).count(), 0);
+
+	// zero sized columns are chunked like the rest of their table
+	for e in entities.iter() {
+		ecs.attach_component(*e, Frozen);
+	}
+	let frozen = ecs.entity_index[&first].0;
+	assert!(ecs.archetypes().get(&frozen).unwrap().chunk_rows() < usize::MAX);
+	let lens: Vec<(usize, usize)> = iter_chunks!(ecs, A, Frozen).map(|(a, f)| (a.len(), f.len())).collect();
+	assert!(lens.len() > 1 && lens.iter().all(|(a, f)| a == f));
+	for e in entities.iter() {
+		ecs.detach_component::<Frozen>(*e);
+	}
+
+	// emptied chunks stay allocated until the world is asked to shrink
+	for e in entities.drain(10..) {
+		ecs.destroy_entity(e);
+	}
+	assert_eq!(ecs.archetypes().get(&a_id).unwrap().chunk_count(), chunks);
+	ecs.shrink_to_fit();
+	let arche = ecs.archetypes().get(&a_id).unwrap();
+	assert_eq!(arche.chunk_count(), 1);
+	assert_eq!(iter_components!(ecs, EntityId, A).map(|(_, a)| a.0).sum::<usize>(), 45);
+}
+
+#[test]
+fn reserve_and_shrink() {
+	let mut ecs = ECS::new();
+	ecs.reserve::<(A, B)>(3000);
+	let a_id = ecs.find_archetype(&[TypeId::of::<A>(), TypeId::of::<B>()]).unwrap();
+	let reserved = ecs.archetypes().get(&a_id).unwrap().capacity();
+	assert!(reserved >= 3000);
+
+	let mut entities: Vec<EntityId> = (0..3000).map(|i| create_entity_from!(ecs, [A(i), B(0.)])).collect();
+	assert_eq!(ecs.archetypes().get(&a_id).unwrap().capacity(), reserved);
+
+	for e in entities[1..].iter() {
+		ecs.destroy_entity(*e);
+	}
+	// removals keep the reservation, and so does collection
+	assert_eq!(ecs.archetypes().get(&a_id).unwrap().capacity(), reserved);
+	ecs.destroy_entity(entities[0]);
+	ecs.compact_archetypes();
+	assert_eq!(ecs.archetypes().get(&a_id).unwrap().capacity(), reserved);
+	entities[0] = create_entity_from!(ecs, [A(0), B(0.)]);
+	ecs.shrink_to_fit();
+	let arche = ecs.archetypes().get(&a_id).unwrap();
+	assert_eq!(arche.chunk_count(), 1);
+	assert_eq!(ecs.get_component::<A>(entities[0]), Some(&A(0)));
+	ecs.destroy_entity(entities[0]);
+	ecs.shrink_to_fit();
+	assert!(ecs.archetypes().iter().all(|(_, arche)| arche.chunk_count() == 0));
+}
+
+#[test]
+fn compact_archetypes() {
+	let (mut ecs, entities) = basic_ecs();
+	let before = ecs.archetypes().len();
+	// a transient combination leaves its node (and the ones on the way) behind
+	let temp = create_entity_from!(ecs, [Health(1), Name("temp".into())]);
+	ecs.destroy_entity(temp);
+	assert!(ecs.archetypes().len() > before);
+
+	let removed = ecs.compact_archetypes();
+	assert!(removed > 0);
+	assert!(ecs.archetypes().iter().all(|(a_id, arche)| arche.len() > 0 || a_id == ecs.archetypes().root()));
+	assert!(!ecs.component_index.contains_key(&TypeId::of::<Health>()));
+	assert_eq!(ecs.compact_archetypes(), 0);
+
+	// survivors keep working and removed combinations are rebuilt on demand
+	assert_eq!(ecs.get_component::<A>(entities[0]), Some(&A(10)));
+	ecs.attach_component(entities[0], Health(3));
+	ecs.detach_component::<B>(entities[0]);
+	assert_eq!(ecs.get_component::<Health>(entities[0]), Some(&Health(3)));
+	assert_eq!(query!(ecs, A, Health).count(), 1);
+
+	// with a limit the graph collects itself instead of growing without bound
+	ecs.set_archetype_limit(Some(12));
+	for i in 0..50 {
+		let e = create_entity_from!(ecs, [A(i), Health(i as u32)]);
+		ecs.attach_component(e, Name(format!("{}", i)));
+		ecs.destroy_entity(e);
+	}
+	assert!(ecs.archetypes().len() <= 14);
+	assert_eq!(query!(ecs, A, Health).count(), 1);
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Flag<const N: usize>;
+
+#[test]
+fn archetype_edges() {
+	let mut ecs = ECS::with_seed(5);
+	for mask in 0u32..64 {
+		let e = ecs.create_entity();
+		// attach in a different order per mask so nodes are reached along different paths
+		let order: Vec<u32> = if mask % 2 == 0 { (0..6).collect() } else { (0..6).rev().collect() };
+		for bit in order.into_iter().filter(|bit| mask & (1 << bit) != 0) {
+			match bit {
+				0 => ecs.attach_component(e, Flag::<0>),
+				1 => ecs.attach_component(e, Flag::<1>),
+				2 => ecs.attach_component(e, Flag::<2>),
+				3 => ecs.attach_component(e, Flag::<3>),
+				4 => ecs.attach_component(e, Flag::<4>),
+				_ => ecs.attach_component(e, Flag::<5>),
+			}
+		}
+		if mask % 3 == 0 {
+			ecs.destroy_entity(e);
+		}
+	}
+	ecs.compact_archetypes();
+	let e = create_entity_from!(ecs, [Flag::<0>, Flag::<1>, Flag::<2>]);
+	ecs.detach_component::<Flag<1>>(e);
+
+	// every pair of nodes one type apart is linked both ways, and no signature appears twice
+	let graph = ecs.archetypes();
+	let nodes: Vec<(ArchetypeId, HashSet<TypeId>)> = graph.iter().map(|(a_id, arche)| (a_id, arche.types().copied().collect())).collect();
+	for (a_id, types) in nodes.iter() {
+		for (b_id, other) in nodes.iter() {
+			if a_id == b_id {
+				continue;
+			}
+			assert_ne!(types, other);
+			if other.len() == types.len() + 1 && types.is_subset(other) {
+				let extra = other.difference(types).next().unwrap();
+				assert_eq!(graph.get_superset(*a_id, extra), Some(*b_id));
+				assert_eq!(graph.get_subset(*b_id, extra), Some(*a_id));
+			}
+		}
+	}
+}
+
+#[test]
+fn signature_lookup() {
+	let mut ecs = ECS::new();
+	let e = ecs.spawn((A(1), B(2.), C(3)));
+	// the bundle lands in its archetype in one step, no {A} or {A, B} nodes along the way
+	assert_eq!(ecs.archetypes().len(), 2);
+	let abc = ecs.find_archetype(&[TypeId::of::<C>(), TypeId::of::<A>(), TypeId::of::<B>()]).unwrap();
+	assert_eq!(ecs.entity_index[&e].0, abc);
+	assert_eq!(ecs.find_archetype(&[TypeId::of::<EntityId>(), TypeId::of::<A>(), TypeId::of::<B>(), TypeId::of::<C>()]), Some(abc));
+	assert_eq!(ecs.find_archetype(&[TypeId::of::<A>()]), None);
+	assert_eq!(ecs.find_archetype(&[]), Some(ecs.archetypes().root()));
+	assert_eq!(ecs.archetype_for::<(C, A, B)>(), abc);
+
+	// nodes made directly still get their edges
+	let ab = ecs.archetype_for::<(A, B)>();
+	assert_eq!(ecs.archetypes().get_superset(ab, &TypeId::of::<C>()), Some(abc));
+	ecs.detach_component::<C>(e);
+	assert_eq!(ecs.entity_index[&e].0, ab);
+	assert_eq!(ecs.get_component::<B>(e), Some(&B(2.)));
+
+	// spawning is one undo step like any other group
+	ecs.begin_undo_group();
+	let f = ecs.spawn((A(7), Name("undo".into())));
+	ecs.end_undo_group();
+	assert_eq!(ecs.get_component::<Name>(f), Some(&Name("undo".into())));
+	ecs.undo();
+	assert!(!ecs.contains_entity(f));
+}
+
+#[test]
+fn bulk_changes() {
+	let mut ecs = ECS::new();
+	let with_b: Vec<EntityId> = (0..300).map(|i| create_entity_from!(ecs, [A(i), B(i as f32)])).collect();
+	let only_a: Vec<EntityId> = (0..200).map(|i| create_entity_from!(ecs, [A(i)])).collect();
+	let other = create_entity_from!(ecs, [C(1)]);
+	ecs.attach_component(only_a[0], Frozen);
+
+	assert_eq!(ecs.insert_for_query(&[TypeId::of::<A>()], Frozen), 500);
+	assert!(with_b.iter().chain(only_a.iter()).all(|e| ecs.get_component::<Frozen>(*e) == Some(&Frozen)));
+	assert_eq!(ecs.get_component::<B>(with_b[123]), Some(&B(123.)));
+	assert_eq!(ecs.get_component::<A>(only_a[199]), Some(&A(199)));
+	assert!(!ecs.has_component::<Frozen>(other));
+	let frozen_tables = ecs.archetypes().iter().filter(|(_, arche)| arche.len() > 0 && arche.get_column(&TypeId::of::<Frozen>()).is_some()).count();
+	assert_eq!(frozen_tables, 2);
+
+	assert_eq!(ecs.remove_for_query::<Frozen>(&[TypeId::of::<B>()]), 300);
+	assert!(!ecs.has_component::<Frozen>(with_b[0]));
+	assert!(ecs.has_component::<Frozen>(only_a[0]));
+	assert_eq!(ecs.get_component::<B>(with_b[299]), Some(&B(299.)));
+
+	assert_eq!(ecs.despawn_query(&[TypeId::of::<B>()]), 300);
+	assert_eq!(ecs.entity_count(), 201);
+	assert!(!ecs.contains_entity(with_b[5]));
+	assert_eq!(ecs.get_component::<A>(only_a[5]), Some(&A(5)));
+
+	// while journaling the same calls fall back to recorded per-entity changes
+	ecs.start_journal();
+	assert_eq!(ecs.remove_for_query::<Frozen>(&[]), 200);
+	assert_eq!(ecs.despawn_query(&[TypeId::of::<C>()]), 1);
+	let journal = ecs.stop_journal().unwrap();
+	assert!(journal.ops.iter().any(|op| matches!(op, JournalOp::Destroy { entity } if *entity == other)));
+	assert_eq!(ecs.entity_count(), 200);
+}
+
+#[test]
+fn removed_values_are_dropped() {
+	use std::rc::Rc;
+	let counter = Rc::new(());
+	let mut ecs = ECS::new();
+	ecs.registry_mut().register::<Option<Rc<()>>>().with_sparse_storage();
+	let entities: Vec<EntityId> = (0..4).map(|_| ecs.spawn((A(0), counter.clone(), Some(counter.clone())))).collect();
+	assert_eq!(Rc::strong_count(&counter), 9);
+
+	// per-entity removal drops just like the bulk paths do
+	ecs.detach_component::<Rc<()>>(entities[0]);
+	ecs.detach_component::<Option<Rc<()>>>(entities[0]);
+	assert_eq!(Rc::strong_count(&counter), 7);
+	ecs.destroy_entity(entities[1]);
+	assert_eq!(Rc::strong_count(&counter), 5);
+
+	// values moved into the undo history are kept alive until the history lets go of them
+	ecs.begin_undo_group();
+	ecs.destroy_entity(entities[2]);
+	ecs.end_undo_group();
+	assert_eq!(Rc::strong_count(&counter), 5);
+	ecs.undo();
+	assert_eq!(ecs.get_component::<Option<Rc<()>>>(entities[2]).map(|v| v.is_some()), Some(true));
+	ecs.clear_history();
+	ecs.destroy_entity(entities[2]);
+	ecs.despawn_query(&[TypeId::of::<A>()]);
+	assert_eq!(Rc::strong_count(&counter), 1);
+}
+
+#[test]
+fn spawn_batch() {
+	let mut ecs = ECS::new();
+	let first = ecs.spawn((A(0), B(0.)));
+	let spawned = ecs.spawn_batch((1..10_000).map(|i| (A(i), B(i as f32))));
+	assert_eq!(spawned.len(), 9_999);
+	assert_eq!(ecs.entity_count(), 10_000);
+	assert_eq!(ecs.archetypes().len(), 2);
+	assert_eq!(ecs.get_component::<A>(spawned[4_999]), Some(&A(5_000)));
+	assert_eq!(ecs.get_component::<B>(spawned[9_998]), Some(&B(9_999.)));
+	assert_eq!(ecs.get_component::<A>(first), Some(&A(0)));
+	assert_eq!(iter_components!(ecs, A, B).filter(|(a, b)| a.0 as f32 == b.0).count(), 10_000);
+
+	// rows stay consistent with the entity index through later structural changes
+	ecs.destroy_entity(spawned[0]);
+	ecs.detach_component::<B>(spawned[100]);
+	assert_eq!(ecs.get_component::<A>(spawned[9_998]), Some(&A(9_999)));
+	assert_eq!(ecs.get_component::<A>(spawned[100]), Some(&A(101)));
+	assert!(ecs.spawn_batch(std::iter::empty::<(A, B)>()).is_empty());
+
+	ecs.begin_undo_group();
+	let recorded = ecs.spawn_batch(vec![(C(1),), (C(2),)]);
+	ecs.end_undo_group();
+	assert_eq!(ecs.get_component::<C>(recorded[1]), Some(&C(2)));
+	ecs.undo();
+	assert!(recorded.iter().all(|e| !ecs.contains_entity(*e)));
+}
+
+#[test]
+fn spawn_batch_panic() {
+	let mut ecs = ECS::new();
+	let kept = ecs.spawn((A(0), Name("kept".into())));
+	let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+		ecs.spawn_batch((1..10).map(|i| if i < 5 { (A(i), Name(format!("{}", i))) } else { panic!("iterator gave up") }));
+	}));
+	// the rows written before the panic are gone again, the table still lines up with its entities
+	assert!(result.is_err());
+	assert_eq!(ecs.entity_count(), 1);
+	let e = ecs.spawn((A(99), Name("next".into())));
+	assert_eq!(ecs.get_component::<A>(e), Some(&A(99)));
+	assert_eq!(ecs.get_component::<Name>(kept), Some(&Name("kept".into())));
+}
+
+#[test]
+#[should_panic(expected = "can't contain an EntityId")]
+fn bundle_with_entity_id() {
+	let mut ecs = ECS::new();
+	let other = ecs.create_entity();
+	ecs.spawn((A(1), other));
+}
+
+#[test]
+#[should_panic(expected = "same component type twice")]
+fn bundle_with_repeated_type() {
+	let mut ecs = ECS::new();
+	ecs.spawn((A(1), B(2.), A(3)));
+}
@@ -0,0 +1,96 @@
+use std::any::TypeId;
+
+use crate::{component_vec::{ComponentVec, OwnedColumn}, registry::drop_fn_of, ComponentRegistry, EntityId, JournalOp, ECS};
+
+enum Command {
+	Spawn(EntityId),
+	// the registration runs on commit, a failed transaction leaves the registry alone
+	Attach(EntityId, OwnedColumn, fn(&mut ComponentRegistry)),
+	Detach(EntityId, TypeId),
+	Despawn(EntityId),
+}
+
+// commands are only staged here, the world is read-only until the closure succeeds
+pub struct Transaction<'a> {
+	ecs: &'a mut ECS,
+	commands: Vec<Command>,
+}
+impl Transaction<'_> {
+	pub fn world(&self) -> &ECS {
+		self.ecs
+	}
+	// sees the commands staged so far, the latest one touching the component wins
+	pub fn get<T:'static>(&self, entity: EntityId) -> Option<&T> {
+		for command in self.commands.iter().rev() {
+			match command {
+				Command::Attach(e, column, _) if *e == entity && column.data.type_id() == TypeId::of::<T>() => return column.data.get::<T>(0),
+				Command::Detach(e, tid) if *e == entity && *tid == TypeId::of::<T>() => return None,
+				Command::Despawn(e) if *e == entity => return None,
+				_ => {},
+			}
+		}
+		self.ecs.get_component::<T>(entity)
+	}
+	pub fn spawn(&mut self) -> EntityId {
+		let eid = EntityId(self.ecs.entity_ids.next());
+		self.commands.push(Command::Spawn(eid));
+		eid
+	}
+	pub fn attach<T:'static>(&mut self, entity: EntityId, component: T) {
+		fn register<T:'static>(registry: &mut ComponentRegistry) {
+			registry.register::<T>();
+		}
+		let column = OwnedColumn { data: ComponentVec::from([component]), drop: drop_fn_of::<T>() };
+		self.commands.push(Command::Attach(entity, column, register::<T>));
+	}
+	pub fn detach<T:'static>(&mut self, entity: EntityId) {
+		self.commands.push(Command::Detach(entity, TypeId::of::<T>()));
+	}
+	pub fn despawn(&mut self, entity: EntityId) {
+		self.commands.push(Command::Despawn(entity));
+	}
+	pub fn len(&self) -> usize {
+		self.commands.len()
+	}
+	pub fn is_empty(&self) -> bool {
+		self.commands.is_empty()
+	}
+}
+
+impl ECS {
+	// all or nothing, an error discards every staged command and hands out the same ids again
+	pub fn transaction<R, E>(&mut self, f: impl FnOnce(&mut Transaction) -> Result<R, E>) -> Result<R, E> {
+		let ids = self.entity_ids.clone();
+		let mut tx = Transaction { ecs: self, commands: Vec::new() };
+		let result = f(&mut tx);
+		let commands = tx.commands;
+		if result.is_err() {
+			self.entity_ids = ids;
+			return result;
+		}
+		for command in commands {
+			match command {
+				Command::Spawn(eid) => {
+					self.record_changes();
+					self.spawn_with_id(eid);
+					self.journal_push(JournalOp::Create { entity: eid });
+					self.undo_spawn(eid);
+				},
+				Command::Attach(eid, column, register) => {
+					// the entity may have been despawned by an earlier command, the value is dropped with its column
+					if !self.contains_entity(eid) {
+						continue;
+					}
+					register(&mut self.registry);
+					let tid = column.data.type_id();
+					self.record_changes();
+					self.put_column(eid, column.into_inner());
+					self.journal_attach(eid, &tid);
+				},
+				Command::Detach(eid, tid) => self.detach_type(eid, &tid),
+				Command::Despawn(eid) => self.destroy_entity(eid),
+			}
+		}
+		result
+	}
+}
@@ -1,9 +1,7 @@
 use std::any::TypeId;
 
 use hashbrown::{HashSet, HashMap};
-use uuid::Uuid;
-
-use crate::{archetype::Archetype, ArchetypeId, EntityId};
+use crate::{archetype::Archetype, component_vec::ComponentVec, ids::IdAllocator, ArchetypeId, EntityId};
 
 pub trait Position {
 	fn types(&self) -> HashSet<TypeId>;
@@ -12,6 +10,7 @@ pub trait Position {
 }
 
 struct Node {
+	id: ArchetypeId,
 	element: Archetype,
 	types: HashSet<TypeId>,
 	subsets: HashMap<TypeId, ArchetypeId>,	// parents
@@ -29,8 +28,9 @@ impl Position for Node {
 	}
 }
 macro_rules! node_from {
-	($($t:ty),+) => {
+	($id:expr, $($t:ty),+) => {
 		Node {
+			id: $id,
 			element: crate::archetype!($($t),+),
 			types: HashSet::from([$(TypeId::of::<$t>()),+]),
 			subsets: HashMap::new(),
@@ -39,110 +39,211 @@ macro_rules! node_from {
 	};
 }
 
+// sorted type list, the same set of types always gives the same key
+fn signature(types: &HashSet<TypeId>) -> Vec<TypeId> {
+	let mut sig: Vec<TypeId> = types.iter().copied().collect();
+	sig.sort();
+	sig
+}
+
+// nodes live in creation order so iteration is reproducible, `index` maps ids onto them
 pub struct TypeGraph {
 	root: ArchetypeId,
-	nodes: HashMap<ArchetypeId, Node>,
+	nodes: Vec<Node>,
+	index: HashMap<ArchetypeId, usize>,
+	signatures: HashMap<Vec<TypeId>, ArchetypeId>,
+	minus_one: HashMap<Vec<TypeId>, Vec<(TypeId, ArchetypeId)>>,	// signature with one type taken out -> (that type, node)
+	ids: IdAllocator,
 }
 impl TypeGraph {
-	pub fn new() -> Self {
-		let root = ArchetypeId(Uuid::new_v4());
-		let mut nodes = HashMap::new();
-		nodes.insert(root, node_from!(EntityId));
-		Self {
+	pub(crate) fn new(mut ids: IdAllocator) -> Self {
+		let root = ArchetypeId(ids.next());
+		let mut graph = Self {
 			root,
-			nodes,
-		}
+			nodes: Vec::new(),
+			index: HashMap::new(),
+			signatures: HashMap::new(),
+			minus_one: HashMap::new(),
+			ids,
+		};
+		graph.push_node(node_from!(root, EntityId));
+		graph
 	}
 	pub fn root(&self) -> ArchetypeId { self.root }
 	#[allow(dead_code)]
 	pub fn positions<'a>(&'a self) -> Box<dyn Iterator<Item = &'a dyn Position> + 'a> {
-		Box::new(self.nodes.values().map(|n| n as &dyn Position))
+		Box::new(self.nodes.iter().map(|n| n as &dyn Position))
 	}
 	#[allow(dead_code)]
 	pub fn positions_mut<'a>(&'a mut self) -> Box<dyn Iterator<Item = &'a mut dyn Position> + 'a> {
-		Box::new(self.nodes.values_mut().map(|n| n as &mut dyn Position))
+		Box::new(self.nodes.iter_mut().map(|n| n as &mut dyn Position))
 	}
-	pub fn get_mut(&mut self, a_id: &ArchetypeId) -> Option<&mut Archetype> {
-		if let Some(node) = self.nodes.get_mut(&a_id) {
-			return Some(&mut node.element);
+	pub fn iter(&self) -> impl Iterator<Item = (ArchetypeId, &Archetype)> {
+		self.nodes.iter().map(|n| (n.id, &n.element))
+	}
+	pub fn len(&self) -> usize {
+		self.nodes.len()
+	}
+	pub fn is_empty(&self) -> bool {
+		self.nodes.is_empty()
+	}
+	fn node(&self, a_id: &ArchetypeId) -> Option<&Node> {
+		self.index.get(a_id).map(|i| &self.nodes[*i])
+	}
+	fn node_mut(&mut self, a_id: &ArchetypeId) -> Option<&mut Node> {
+		self.index.get(a_id).map(|i| &mut self.nodes[*i])
+	}
+	fn push_node(&mut self, node: Node) {
+		let sig = signature(&node.types);
+		for (i, ty) in sig.iter().enumerate() {
+			let mut without = sig.clone();
+			without.remove(i);
+			self.minus_one.entry(without).or_default().push((*ty, node.id));
 		}
-		None
+		self.signatures.insert(sig, node.id);
+		self.index.insert(node.id, self.nodes.len());
+		self.nodes.push(node);
+	}
+	pub fn get_mut(&mut self, a_id: &ArchetypeId) -> Option<&mut Archetype> {
+		self.node_mut(a_id).map(|n| &mut n.element)
 	}
 	pub fn get(&self, a_id: &ArchetypeId) -> Option<&Archetype> {
-		if let Some(node) = self.nodes.get(&a_id) {
-			return Some(&node.element);
-		}
-		None
+		self.node(a_id).map(|n| &n.element)
 	}
 	pub fn get_many<const W:usize>(&mut self, ids: [&ArchetypeId;W]) -> Option<[&mut Archetype;W]> {
-		if let Some(nodes) = self.nodes.get_many_mut(ids) {
-			Some(nodes.map(|n| &mut n.element))
-		} else {
-			None
+		let mut idx = [0; W];
+		for (i, id) in ids.iter().enumerate() {
+			idx[i] = *self.index.get(*id)?;
+			if idx[..i].contains(&idx[i]) {
+				return None;
+			}
 		}
+		// the indices are in bounds and distinct, so the borrows never alias
+		let nodes = self.nodes.as_mut_ptr();
+		Some(idx.map(|i| unsafe { &mut (*nodes.add(i)).element }))
+	}
+	// the node holding exactly these types, the entity id column is implied
+	pub fn find_archetype(&self, types: &[TypeId]) -> Option<ArchetypeId> {
+		let mut set: HashSet<TypeId> = types.iter().copied().collect();
+		set.insert(TypeId::of::<EntityId>());
+		self.signatures.get(&signature(&set)).copied()
+	}
+	// builds a node straight from its columns, skipping the intermediate steps an edge walk would create
+	pub fn create_archetype(&mut self, columns: &[ComponentVec]) -> ArchetypeId {
+		let mut element = crate::archetype!(EntityId);
+		let mut types = HashSet::from([TypeId::of::<EntityId>()]);
+		for column in columns.iter().filter(|c| c.type_id() != TypeId::of::<EntityId>()) {
+			element.add_column(column);
+			types.insert(column.type_id());
+		}
+		assert!(!self.signatures.contains_key(&signature(&types)), "Archetype already exists");
+
+		let new_id = ArchetypeId(self.ids.next());
+		let mut new_node = Node { id: new_id, element, types, subsets: HashMap::new(), supsets: HashMap::new() };
+		self.connect_neighbors(new_id, &mut new_node);
+
+		self.push_node(new_node);
+		new_id
 	}
 	pub fn get_superset_with<T:'static>(&self, src: ArchetypeId) -> Option<ArchetypeId> {
-		let src = self.nodes.get(&src).unwrap();
-		src.supsets.get(&TypeId::of::<T>()).copied()
+		self.get_superset(src, &TypeId::of::<T>())
+	}
+	pub fn get_superset(&self, src: ArchetypeId, tid: &TypeId) -> Option<ArchetypeId> {
+		let src = self.node(&src).unwrap();
+		src.supsets.get(tid).copied()
 	}
 	pub fn get_subset_without<T:'static>(&self, src: ArchetypeId) -> Option<ArchetypeId> {
-		let src = self.nodes.get(&src).unwrap();
-		src.subsets.get(&TypeId::of::<T>()).copied()
+		self.get_subset(src, &TypeId::of::<T>())
+	}
+	pub fn get_subset(&self, src: ArchetypeId, tid: &TypeId) -> Option<ArchetypeId> {
+		let src = self.node(&src).unwrap();
+		src.subsets.get(tid).copied()
 	}
 	pub fn create_superset_with<T:'static>(&mut self, src: ArchetypeId) -> ArchetypeId {
-		let sub = self.nodes.get(&src).unwrap();
+		self.create_superset(src, &ComponentVec::new_as::<T>())
+	}
+	pub fn create_superset(&mut self, src: ArchetypeId, column: &ComponentVec) -> ArchetypeId {
+		let sub = self.node(&src).unwrap();
+		let tid = column.type_id();
 
 		let mut element = sub.element.imitate();
-		element.add_component::<T>();
+		element.add_column(column);
 
 		let mut types = sub.types.clone();
-		types.insert(TypeId::of::<T>());
+		types.insert(tid);
 
-		let new_id = ArchetypeId(Uuid::new_v4());
+		let new_id = ArchetypeId(self.ids.next());
 		let mut subsets = HashMap::new();
-		subsets.insert(TypeId::of::<T>(), src);
-		let mut new_node = Node { element, types, subsets, supsets: HashMap::new() };
+		subsets.insert(tid, src);
+		let mut new_node = Node { id: new_id, element, types, subsets, supsets: HashMap::new() };
 		self.connect_neighbors(new_id, &mut new_node);
 
-		self.nodes.insert(new_id, new_node);
+		self.push_node(new_node);
 		new_id
 	}
 	pub fn create_subset_without<T:'static>(&mut self, src: ArchetypeId) -> ArchetypeId {
-		let sup = self.nodes.get(&src).unwrap();
+		self.create_subset(src, &TypeId::of::<T>())
+	}
+	pub fn create_subset(&mut self, src: ArchetypeId, tid: &TypeId) -> ArchetypeId {
+		let sup = self.node(&src).unwrap();
 
 		let mut element = sup.element.imitate();
-		element.remove_component::<T>();
+		element.remove_column(tid);
 
 		let mut types = sup.types.clone();
-		types.remove(&TypeId::of::<T>());
+		types.remove(tid);
 
-		let new_id = ArchetypeId(Uuid::new_v4());
+		let new_id = ArchetypeId(self.ids.next());
 		let mut supsets = HashMap::new();
-		supsets.insert(TypeId::of::<T>(), src);
-		let mut new_node = Node { element, types, subsets: HashMap::new(), supsets };
+		supsets.insert(*tid, src);
+		let mut new_node = Node { id: new_id, element, types, subsets: HashMap::new(), supsets };
 		self.connect_neighbors(new_id, &mut new_node);
 
-		self.nodes.insert(new_id, new_node);
+		self.push_node(new_node);
 		new_id
 	}
+	// drops the nodes `keep` rejects along with every edge pointing at them, the root always stays
+	pub(crate) fn retain(&mut self, mut keep: impl FnMut(ArchetypeId, &Archetype) -> bool) -> Vec<ArchetypeId> {
+		let root = self.root;
+		let mut removed = Vec::new();
+		self.nodes.retain(|node| {
+			let kept = node.id == root || keep(node.id, &node.element);
+			if !kept {
+				removed.push(node.id);
+			}
+			kept
+		});
+		if removed.is_empty() {
+			return removed;
+		}
+		let gone: HashSet<ArchetypeId> = removed.iter().copied().collect();
+		self.signatures.retain(|_, a_id| !gone.contains(a_id));
+		self.minus_one.retain(|_, nodes| {
+			nodes.retain(|(_, a_id)| !gone.contains(a_id));
+			!nodes.is_empty()
+		});
+		self.index.clear();
+		for (i, node) in self.nodes.iter_mut().enumerate() {
+			node.subsets.retain(|_, a_id| !gone.contains(a_id));
+			node.supsets.retain(|_, a_id| !gone.contains(a_id));
+			self.index.insert(node.id, i);
+		}
+		removed
+	}
+	// neighbors differ by exactly one type, so both directions are a hash lookup away
 	fn connect_neighbors(&mut self, a_id: ArchetypeId, target: &mut Node) {
-		let defer: Vec<ArchetypeId> = self.nodes.iter().map(|e| e.0.clone()).collect();
-		for cur in defer {
-			let node = self.nodes.get_mut(&cur).unwrap();
-			if target.types.len().abs_diff(node.types.len()) == 1 {
-				if node.types.is_subset(&target.types) {
-					for ty in target.types.difference(&node.types).cloned() {
-						node.supsets.insert(ty, a_id);
-						target.subsets.insert(ty, cur);
-					}
-				}
-				if node.types.is_superset(&target.types) {
-					for ty in node.types.difference(&target.types).cloned() {
-						node.subsets.insert(ty, a_id);
-						target.supsets.insert(ty, cur);
-					}
-				}
+		let sig = signature(&target.types);
+		for (i, ty) in sig.iter().enumerate() {
+			let mut without = sig.clone();
+			without.remove(i);
+			if let Some(sub) = self.signatures.get(&without).copied() {
+				self.node_mut(&sub).unwrap().supsets.insert(*ty, a_id);
+				target.subsets.insert(*ty, sub);
 			}
 		}
+		for (ty, sup) in self.minus_one.get(&sig).cloned().unwrap_or_default() {
+			self.node_mut(&sup).unwrap().subsets.insert(ty, a_id);
+			target.supsets.insert(ty, sup);
+		}
 	}
-}
\ No newline at end of file
+}
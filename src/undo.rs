@@ -0,0 +1,155 @@
+use std::any::TypeId;
+
+use crate::{component_vec::{ComponentVec, OwnedColumn}, EntityId, JournalOp, ECS};
+
+// every op is its own inverse once applied, swapping what the world holds with what the op holds
+enum UndoOp {
+	Swap { entity: EntityId, tid: TypeId, value: Option<OwnedColumn> },
+	Spawn(EntityId),
+	Despawn(EntityId),
+}
+
+#[derive(Default)]
+pub(crate) struct History {
+	undo: Vec<Vec<UndoOp>>,
+	redo: Vec<Vec<UndoOp>>,
+	open: Option<Vec<UndoOp>>,
+	depth: usize,
+}
+impl History {
+	fn record(&mut self, op: UndoOp) {
+		if let Some(group) = self.open.as_mut() {
+			group.push(op);
+		}
+	}
+}
+
+impl ECS {
+	// groups nest, only the outermost end commits
+	pub fn begin_undo_group(&mut self) {
+		if self.history.depth == 0 {
+			self.history.open = Some(Vec::new());
+		}
+		self.history.depth += 1;
+	}
+	pub fn end_undo_group(&mut self) {
+		assert!(self.history.depth > 0, "No undo group to end");
+		self.history.depth -= 1;
+		if self.history.depth == 0 {
+			let group = self.history.open.take().unwrap();
+			if !group.is_empty() {
+				self.history.undo.push(group);
+				self.history.redo.clear();
+			}
+		}
+	}
+	pub fn is_recording_undo(&self) -> bool {
+		self.history.open.is_some()
+	}
+	pub fn can_undo(&self) -> bool {
+		!self.history.undo.is_empty()
+	}
+	pub fn can_redo(&self) -> bool {
+		!self.history.redo.is_empty()
+	}
+	pub fn clear_history(&mut self) {
+		self.history.undo.clear();
+		self.history.redo.clear();
+	}
+	pub fn undo(&mut self) -> bool {
+		assert!(!self.is_recording_undo(), "Cannot undo inside an undo group");
+		let Some(mut group) = self.history.undo.pop() else {
+			return false;
+		};
+		for op in group.iter_mut().rev() {
+			self.toggle(op);
+		}
+		self.history.redo.push(group);
+		true
+	}
+	pub fn redo(&mut self) -> bool {
+		assert!(!self.is_recording_undo(), "Cannot redo inside an undo group");
+		let Some(mut group) = self.history.redo.pop() else {
+			return false;
+		};
+		for op in group.iter_mut() {
+			self.toggle(op);
+		}
+		self.history.undo.push(group);
+		true
+	}
+	// edits through references can't be seen, so recorded edits go through a copy
+	pub fn edit_component<T:'static + Clone>(&mut self, entity: EntityId, edit: impl FnOnce(&mut T)) -> bool {
+		let Some(mut value) = self.get_component::<T>(entity).cloned() else {
+			return false;
+		};
+		edit(&mut value);
+		self.attach_component(entity, value);
+		true
+	}
+	// a journal records undo and redo as the edits they make to the world
+	fn toggle(&mut self, op: &mut UndoOp) {
+		self.record_changes();
+		match op {
+			UndoOp::Swap { entity, tid, value } => {
+				let attaching = value.is_some();
+				if !attaching {
+					self.journal_detach(*entity, tid);
+				}
+				*value = self.swap_column(*entity, tid, value.take());
+				if attaching {
+					self.journal_attach(*entity, tid);
+				}
+			},
+			UndoOp::Spawn(entity) => {
+				if self.contains_entity(*entity) {
+					self.journal_push(JournalOp::Destroy { entity: *entity });
+				}
+				self.despawn(*entity);
+				*op = UndoOp::Despawn(*entity);
+			},
+			UndoOp::Despawn(entity) => {
+				self.spawn_with_id(*entity);
+				self.journal_push(JournalOp::Create { entity: *entity });
+				*op = UndoOp::Spawn(*entity);
+			},
+		}
+	}
+	fn swap_column(&mut self, entity: EntityId, tid: &TypeId, value: Option<OwnedColumn>) -> Option<OwnedColumn> {
+		if !self.contains_entity(entity) {
+			return None;
+		}
+		let drop = self.registry.get(tid).and_then(|info| info.drop_fn());
+		let old = self.take_column(entity, tid).map(|data| OwnedColumn { data, drop });
+		if let Some(mut value) = value {
+			self.attach_column(entity, &mut value.data);
+		}
+		old
+	}
+	pub(crate) fn undo_spawn(&mut self, entity: EntityId) {
+		self.history.record(UndoOp::Spawn(entity));
+	}
+	// empties the entity so a later undo can bring every component back
+	pub(crate) fn undo_despawn(&mut self, entity: EntityId) {
+		if !self.is_recording_undo() {
+			return;
+		}
+		for tid in self.component_types(entity) {
+			if tid != TypeId::of::<EntityId>() {
+				self.undo_swap(entity, &tid, None);
+			}
+		}
+		self.history.record(UndoOp::Despawn(entity));
+	}
+	pub(crate) fn undo_swap(&mut self, entity: EntityId, tid: &TypeId, column: Option<ComponentVec>) {
+		if !self.contains_entity(entity) {
+			return;
+		}
+		let attaching = column.is_some();
+		let value = column.map(|data| OwnedColumn { data, drop: self.registry.get(tid).and_then(|info| info.drop_fn()) });
+		let old = self.swap_column(entity, tid, value);
+		if attaching || old.is_some() {
+			self.history.record(UndoOp::Swap { entity, tid: *tid, value: old });
+		}
+	}
+}